@@ -0,0 +1,151 @@
+use bevy::prelude::*;
+
+use crate::graph::{all_pairs_shortest_paths, connected_components, Adjacency, Dot, Neighbors};
+
+/// Summary statistics of the graph described by every Dot's `Neighbors`, treated as undirected
+#[derive(Resource, Debug, Default)]
+pub struct GraphStats {
+    pub nodes: usize,
+    pub edges: usize,
+    pub components: usize,
+    pub largest_component: usize,
+    /// How many nodes have each degree, indexed by degree
+    pub degree_histogram: Vec<usize>,
+    pub average_degree: f32,
+    /// The mean of every node's local clustering coefficient, counting nodes with fewer than two
+    /// neighbors as 0
+    pub average_clustering: f32,
+    /// The longest shortest path within any component, in hops
+    pub diameter: usize,
+    /// The fraction of all possible edges that exist
+    pub density: f32,
+}
+
+impl GraphStats {
+    pub fn from_adjacency(adjacency: &Adjacency) -> Self {
+        let n = adjacency.len();
+        if n == 0 {
+            return GraphStats::default();
+        }
+
+        let degrees: Vec<usize> = adjacency.adjacent.iter().map(|adjacent| adjacent.len()).collect();
+        let edges = degrees.iter().sum::<usize>() / 2;
+
+        let mut degree_histogram = vec![0; degrees.iter().max().cloned().unwrap_or(0) + 1];
+        for &degree in degrees.iter() {
+            degree_histogram[degree] += 1;
+        }
+
+        let component = connected_components(adjacency);
+        let components = component.iter().max().map_or(0, |max| max + 1);
+        // components are numbered from largest to smallest
+        let largest_component = component.iter().filter(|&&c| c == 0).count();
+
+        let average_clustering = (0..n).map(|i| local_clustering(adjacency, i)).sum::<f32>() / n as f32;
+
+        let diameter = all_pairs_shortest_paths(&adjacency.adjacent)
+            .iter()
+            .flatten()
+            .filter(|d| d.is_finite())
+            .fold(0., |a: f32, &b| a.max(b)) as usize;
+
+        let density = if n > 1 { 2. * edges as f32 / (n * (n - 1)) as f32 } else { 0. };
+
+        GraphStats {
+            nodes: n,
+            edges,
+            components,
+            largest_component,
+            degree_histogram,
+            average_degree: 2. * edges as f32 / n as f32,
+            average_clustering,
+            diameter,
+            density,
+        }
+    }
+}
+
+/// The fraction of pairs of a node's neighbors which are also neighbors of each other
+fn local_clustering(adjacency: &Adjacency, node: usize) -> f32 {
+    let neighbors = &adjacency.adjacent[node];
+    let k = neighbors.len();
+    if k < 2 {
+        return 0.;
+    }
+
+    let mut links = 0;
+    for (i, &a) in neighbors.iter().enumerate() {
+        for &b in neighbors[i + 1..].iter() {
+            if adjacency.adjacent[a].contains(&b) {
+                links += 1;
+            }
+        }
+    }
+    2. * links as f32 / (k * (k - 1)) as f32
+}
+
+/// Recomputes `GraphStats` whenever any Dot's `Neighbors` change or Dots are added or removed
+pub fn update_graph_stats(
+    q: Query<(Entity, &Neighbors), With<Dot>>,
+    changed: Query<(), Changed<Neighbors>>,
+    mut removed: RemovedComponents<Dot>,
+    mut stats: ResMut<GraphStats>,
+) {
+    let removed = removed.read().count() > 0;
+    if changed.is_empty() && !removed && stats.nodes == q.iter().len() {
+        return;
+    }
+
+    *stats = GraphStats::from_adjacency(&Adjacency::from_neighbors(q.iter()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An undirected `Adjacency` from a list of edges
+    fn graph(n: usize, edges: &[(usize, usize)]) -> Adjacency {
+        let mut adjacent = vec![Vec::new(); n];
+        for &(a, b) in edges {
+            adjacent[a].push(b);
+            adjacent[b].push(a);
+        }
+        Adjacency { entities: (0..n as u32).map(Entity::from_raw).collect(), adjacent }
+    }
+
+    #[test]
+    fn triangle_is_fully_clustered() {
+        let stats = GraphStats::from_adjacency(&graph(3, &[(0, 1), (1, 2), (2, 0)]));
+        assert_eq!((stats.nodes, stats.edges), (3, 3));
+        assert_eq!(stats.average_clustering, 1.);
+        assert_eq!(stats.diameter, 1);
+        assert_eq!(stats.density, 1.);
+        assert_eq!(stats.degree_histogram, vec![0, 0, 3]);
+    }
+
+    #[test]
+    fn paths_have_no_clustering_and_span_their_length() {
+        let stats = GraphStats::from_adjacency(&graph(5, &[(0, 1), (1, 2), (2, 3), (3, 4)]));
+        assert_eq!(stats.average_clustering, 0.);
+        assert_eq!(stats.diameter, 4);
+        assert_eq!(stats.components, 1);
+        assert_eq!(stats.degree_histogram, vec![0, 2, 3]);
+    }
+
+    #[test]
+    fn components_are_counted_and_diameter_ignores_unreachable_pairs() {
+        // a path of 3, an edge and an isolated node
+        let stats = GraphStats::from_adjacency(&graph(6, &[(0, 1), (1, 2), (3, 4)]));
+        assert_eq!(stats.components, 3);
+        assert_eq!(stats.largest_component, 3);
+        assert_eq!(stats.diameter, 2);
+        assert_eq!(stats.degree_histogram, vec![1, 4, 1]);
+    }
+
+    #[test]
+    fn open_triads_lower_clustering() {
+        // a triangle with a pendant on node 0: node 0 has 1 of 3 possible links between its neighbors
+        let stats = GraphStats::from_adjacency(&graph(4, &[(0, 1), (1, 2), (2, 0), (0, 3)]));
+        assert!((stats.average_clustering - (1. / 3. + 1. + 1. + 0.) / 4.).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,306 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{geometry::{closest_point_on_polygon, closest_point_on_segment, point_in_polygon}, graph::Dot, physics::Velocity, WIN_SIZE};
+
+/// How much of their speed Dots keep when bouncing off the boundary
+const BOUNDARY_RESTITUTION: f32 = 0.8;
+/// Keeps walls, and the outlines of obstacles that repel, from pushing infinitely hard on Dots
+/// right against them
+pub const MIN_WALL_DISTANCE: f32 = 0.0001;
+/// How many sides the default polygon boundary has
+const POLYGON_SIDES: usize = 6;
+
+/// The region Dots are kept inside, or kept out of when it's an obstacle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BoundaryShape {
+    Rectangle { min: Vec2, max: Vec2 },
+    Circle { center: Vec2, radius: f32 },
+    /// Vertices in order around the outline
+    Polygon { vertices: Vec<Vec2> },
+    /// Dots can go anywhere
+    None,
+}
+
+impl BoundaryShape {
+    pub fn window_rectangle() -> Self {
+        BoundaryShape::Rectangle { min: Vec2::ZERO, max: Vec2::new(WIN_SIZE.0, WIN_SIZE.1) }
+    }
+
+    /// The largest circle that fits in the window
+    pub fn window_circle() -> Self {
+        BoundaryShape::Circle { center: Vec2::new(WIN_SIZE.0, WIN_SIZE.1) / 2., radius: WIN_SIZE.0.min(WIN_SIZE.1) / 2. }
+    }
+
+    /// A regular polygon that fits in the window
+    pub fn window_polygon() -> Self {
+        let center = Vec2::new(WIN_SIZE.0, WIN_SIZE.1) / 2.;
+        let radius = WIN_SIZE.0.min(WIN_SIZE.1) / 2.;
+        let vertices = (0..POLYGON_SIDES)
+            .map(|i| center + Vec2::from_angle(i as f32 / POLYGON_SIDES as f32 * TAU) * radius)
+            .collect();
+        BoundaryShape::Polygon { vertices }
+    }
+
+    /// The smallest rectangle containing the shape
+    pub fn bounds(&self) -> Option<Rect> {
+        match self {
+            BoundaryShape::Rectangle { min, max } => Some(Rect::from_corners(*min, *max)),
+            BoundaryShape::Circle { center, radius } => Some(Rect::from_center_half_size(*center, Vec2::splat(*radius))),
+            BoundaryShape::Polygon { vertices } if !vertices.is_empty() => {
+                Some(vertices.iter().fold(Rect::from_corners(vertices[0], vertices[0]), |rect, &v| rect.union_point(v)))
+            }
+            _ => None,
+        }
+    }
+
+    /// The middle of the shape, or of the window if there isn't one
+    pub fn center(&self) -> Vec2 {
+        match self {
+            BoundaryShape::Circle { center, .. } => *center,
+            _ => self.bounds().map_or(Vec2::new(WIN_SIZE.0, WIN_SIZE.1) / 2., |bounds| bounds.center()),
+        }
+    }
+
+    /// Whether the shape encloses anything. An open boundary or a polygon with fewer than 3
+    /// vertices doesn't, and lets Dots go anywhere
+    pub fn is_closed(&self) -> bool {
+        match self {
+            BoundaryShape::Polygon { vertices } => vertices.len() >= 3,
+            BoundaryShape::None => false,
+            _ => true,
+        }
+    }
+
+    pub fn contains(&self, p: Vec2) -> bool {
+        match self {
+            BoundaryShape::Rectangle { min, max } => Rect::from_corners(*min, *max).contains(p),
+            BoundaryShape::Circle { center, radius } => p.distance_squared(*center) <= radius * radius,
+            BoundaryShape::Polygon { vertices } => vertices.len() < 3 || point_in_polygon(p, vertices),
+            BoundaryShape::None => true,
+        }
+    }
+
+    /// The point on the outline closest to `p`
+    pub fn closest_point(&self, p: Vec2) -> Option<Vec2> {
+        match self {
+            BoundaryShape::Rectangle { min, max } => {
+                let rect = Rect::from_corners(*min, *max);
+                if !rect.contains(p) {
+                    return Some(p.clamp(rect.min, rect.max));
+                }
+                // inside, so whichever wall is nearest
+                let walls = [
+                    Vec2::new(rect.min.x, p.y),
+                    Vec2::new(rect.max.x, p.y),
+                    Vec2::new(p.x, rect.min.y),
+                    Vec2::new(p.x, rect.max.y),
+                ];
+                walls.into_iter().min_by(|a, b| a.distance_squared(p).total_cmp(&b.distance_squared(p)))
+            }
+            BoundaryShape::Circle { center, radius } => {
+                let direction = (p - *center).try_normalize().unwrap_or(Vec2::X);
+                Some(*center + direction * *radius)
+            }
+            BoundaryShape::Polygon { vertices } => closest_point_on_polygon(p, vertices),
+            BoundaryShape::None => None,
+        }
+    }
+
+    /// The inverse-square push of every wall on a Dot at `p`, away from the wall and into the shape
+    pub fn wall_force(&self, p: Vec2, strength: f32) -> Vec2 {
+        let push = |distance: f32| strength / distance.max(MIN_WALL_DISTANCE).powi(2);
+        match self {
+            BoundaryShape::Rectangle { min, max } => {
+                let (min, max) = (min.min(*max), min.max(*max));
+                Vec2::new(
+                    push(p.x - min.x) - push(max.x - p.x),
+                    push(p.y - min.y) - push(max.y - p.y),
+                )
+            }
+            BoundaryShape::Circle { center, radius } => {
+                let offset = p - *center;
+                -offset.normalize_or_zero() * push(radius - offset.length())
+            }
+            BoundaryShape::Polygon { vertices } => {
+                if !self.contains(p) {
+                    // straight back towards the nearest wall, as hard as possible
+                    return self.closest_point(p).map_or(Vec2::ZERO, |closest| (closest - p).normalize_or_zero() * push(0.));
+                }
+                (0..vertices.len())
+                    .map(|i| {
+                        let closest = closest_point_on_segment(p, vertices[i], vertices[(i + 1) % vertices.len()]);
+                        (p - closest).normalize_or_zero() * push(p.distance(closest))
+                    })
+                    .sum()
+            }
+            BoundaryShape::None => Vec2::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WallBehaviour {
+    /// Walls push Dots away, harder the closer they get
+    SoftRepulsion,
+    /// Dots that leave are put back on the wall and stop moving outwards
+    Clamp,
+    /// Dots that leave are put back on the wall and bounce off it
+    Bounce,
+    /// Dots that leave one side come back in the other, across the shape's bounding rectangle.
+    /// Forces and collisions between Dots reach across the edges too, as if it were a torus
+    Wrap,
+}
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Boundary {
+    pub shape: BoundaryShape,
+    pub behaviour: WallBehaviour,
+    /// Used when bouncing
+    pub restitution: f32,
+}
+
+impl Default for Boundary {
+    fn default() -> Self {
+        Boundary {
+            shape: BoundaryShape::window_rectangle(),
+            behaviour: WallBehaviour::SoftRepulsion,
+            restitution: BOUNDARY_RESTITUTION,
+        }
+    }
+}
+
+impl Boundary {
+    /// The rectangle Dots wrap around, if they do
+    pub fn wrap_bounds(&self) -> Option<Rect> {
+        if self.behaviour != WallBehaviour::Wrap {
+            return None;
+        }
+        self.shape.bounds().filter(|bounds| !bounds.is_empty())
+    }
+}
+
+/// The shortest offset from `from` to `to`, which when wrapping may go out one side of the
+/// rectangle and come back in the other
+pub fn wrapped_offset(from: Vec2, to: Vec2, wrap: Option<Rect>) -> Vec2 {
+    let offset = to - from;
+    wrap.map_or(offset, |bounds| offset - bounds.size() * (offset / bounds.size()).round())
+}
+
+/// Puts Dots that have left the boundary back inside, by clamping, bouncing or wrapping them
+pub fn enforce_boundary(
+    mut q: Query<(&mut Transform, &mut Velocity), With<Dot>>,
+    boundary: Res<Boundary>,
+) {
+    if boundary.behaviour == WallBehaviour::SoftRepulsion {
+        return;
+    }
+
+    for (mut tf, mut vel) in q.iter_mut() {
+        let mut pos = tf.translation.xy();
+        if let Some(bounds) = boundary.wrap_bounds() {
+            pos = bounds.min + (pos - bounds.min).rem_euclid(bounds.size());
+        }
+
+        // wrapping across the bounding rectangle can still leave a Dot outside other shapes
+        if !boundary.shape.contains(pos) {
+            let Some(closest) = boundary.shape.closest_point(pos) else { continue };
+            let inwards = (closest - pos).normalize_or_zero();
+            let v = vel.0.xy();
+            let outwards_speed = v.dot(inwards).min(0.);
+            let v = match boundary.behaviour {
+                WallBehaviour::Bounce => v - inwards * outwards_speed * (1. + boundary.restitution),
+                _ => v - inwards * outwards_speed,
+            };
+            vel.0 = v.extend(vel.0.z);
+            pos = closest;
+        }
+
+        if pos != tf.translation.xy() {
+            tf.translation = pos.extend(tf.translation.z);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    /// Runs `enforce_boundary` on a single Dot, returning where it ends up and how fast it's going
+    fn enforce(boundary: Boundary, pos: Vec2, vel: Vec2) -> (Vec2, Vec2) {
+        let mut world = World::new();
+        world.insert_resource(boundary);
+        let dot = world.spawn((Dot, Transform::from_translation(pos.extend(0.)), Velocity(vel.extend(0.)))).id();
+        world.run_system_once(enforce_boundary);
+        let dot = world.entity(dot);
+        (dot.get::<Transform>().unwrap().translation.xy(), dot.get::<Velocity>().unwrap().0.xy())
+    }
+
+    fn rectangle(behaviour: WallBehaviour) -> Boundary {
+        Boundary {
+            shape: BoundaryShape::Rectangle { min: Vec2::ZERO, max: Vec2::new(100., 50.) },
+            behaviour,
+            restitution: 0.5,
+        }
+    }
+
+    #[test]
+    fn wrapped_offsets_take_the_shortest_way() {
+        let wrap = rectangle(WallBehaviour::Wrap).wrap_bounds();
+        assert_eq!(wrapped_offset(Vec2::new(95., 10.), Vec2::new(5., 10.), wrap), Vec2::new(10., 0.));
+        assert_eq!(wrapped_offset(Vec2::new(5., 45.), Vec2::new(95., 5.), wrap), Vec2::new(-10., 10.));
+        // nearer directly than around
+        assert_eq!(wrapped_offset(Vec2::new(20., 10.), Vec2::new(60., 30.), wrap), Vec2::new(40., 20.));
+        // without wrapping, always directly
+        assert_eq!(wrapped_offset(Vec2::new(95., 10.), Vec2::new(5., 10.), None), Vec2::new(-90., 0.));
+        assert_eq!(rectangle(WallBehaviour::Bounce).wrap_bounds(), None);
+    }
+
+    #[test]
+    fn circles_and_polygons_contain_their_insides() {
+        let circle = BoundaryShape::Circle { center: Vec2::new(10., 10.), radius: 5. };
+        assert!(circle.contains(Vec2::new(13., 13.)));
+        assert!(!circle.contains(Vec2::new(14., 14.)));
+        assert_eq!(circle.closest_point(Vec2::new(30., 10.)), Some(Vec2::new(15., 10.)));
+
+        // an L shape, which isn't convex
+        let polygon = BoundaryShape::Polygon {
+            vertices: vec![Vec2::ZERO, Vec2::new(20., 0.), Vec2::new(20., 10.), Vec2::new(10., 10.), Vec2::new(10., 20.), Vec2::new(0., 20.)],
+        };
+        assert!(polygon.contains(Vec2::new(5., 15.)));
+        assert!(polygon.contains(Vec2::new(15., 5.)));
+        assert!(!polygon.contains(Vec2::new(15., 15.)));
+        assert_eq!(polygon.closest_point(Vec2::new(15., 12.)), Some(Vec2::new(15., 10.)));
+
+        // too few vertices to enclose anything
+        let open = BoundaryShape::Polygon { vertices: vec![Vec2::ZERO, Vec2::X] };
+        assert!(!open.is_closed());
+        assert!(open.contains(Vec2::splat(100.)));
+    }
+
+    #[test]
+    fn bouncing_reflects_outwards_velocity() {
+        let (pos, vel) = enforce(rectangle(WallBehaviour::Bounce), Vec2::new(110., 20.), Vec2::new(4., 1.));
+        assert_eq!(pos, Vec2::new(100., 20.));
+        // back inwards at half the speed, without changing speed along the wall
+        assert_eq!(vel, Vec2::new(-2., 1.));
+    }
+
+    #[test]
+    fn clamping_stops_outwards_velocity() {
+        let (pos, vel) = enforce(rectangle(WallBehaviour::Clamp), Vec2::new(50., -5.), Vec2::new(1., -3.));
+        assert_eq!(pos, Vec2::new(50., 0.));
+        assert_eq!(vel, Vec2::new(1., 0.));
+    }
+
+    #[test]
+    fn wrapping_comes_back_in_the_other_side() {
+        let (pos, vel) = enforce(rectangle(WallBehaviour::Wrap), Vec2::new(110., -5.), Vec2::new(4., -1.));
+        assert_eq!(pos, Vec2::new(10., 45.));
+        assert_eq!(vel, Vec2::new(4., -1.));
+    }
+}
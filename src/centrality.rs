@@ -0,0 +1,300 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::graph::{Adjacency, Dot, Neighbors};
+
+/// The chance PageRank's random surfer follows an edge rather than jumping anywhere
+const PAGERANK_DAMPING: f32 = 0.85;
+/// Power iteration stops once no score moves by more than this
+const POWER_ITERATION_TOLERANCE: f32 = 1e-6;
+/// Gives up on power iteration if it hasn't converged after this many rounds
+const POWER_ITERATION_MAX_ROUNDS: usize = 200;
+
+/// How central a Dot is in the graph described by every Dot's `Neighbors`, by several measures
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Centrality {
+    /// The fraction of the other Dots this one is a neighbor of
+    pub degree: f32,
+    /// The fraction of shortest paths between other pairs of Dots that pass through this one
+    pub betweenness: f32,
+    /// How close this Dot is to every other Dot it can reach, scaled down by how few it can reach
+    pub closeness: f32,
+    /// Central Dots are the ones connected to other central Dots
+    pub eigenvector: f32,
+    /// How often a random walk which sometimes jumps anywhere ends up at this Dot
+    pub pagerank: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CentralityMeasure {
+    Degree,
+    Betweenness,
+    Closeness,
+    Eigenvector,
+    PageRank,
+}
+
+impl CentralityMeasure {
+    pub const ALL: [CentralityMeasure; 5] = [
+        CentralityMeasure::Degree,
+        CentralityMeasure::Betweenness,
+        CentralityMeasure::Closeness,
+        CentralityMeasure::Eigenvector,
+        CentralityMeasure::PageRank,
+    ];
+
+    pub fn value(&self, centrality: &Centrality) -> f32 {
+        match self {
+            CentralityMeasure::Degree => centrality.degree,
+            CentralityMeasure::Betweenness => centrality.betweenness,
+            CentralityMeasure::Closeness => centrality.closeness,
+            CentralityMeasure::Eigenvector => centrality.eigenvector,
+            CentralityMeasure::PageRank => centrality.pagerank,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            // not just "Degree", which is easily confused with the raw neighbor count
+            CentralityMeasure::Degree => "Degree Centrality",
+            CentralityMeasure::Betweenness => "Betweenness",
+            CentralityMeasure::Closeness => "Closeness",
+            CentralityMeasure::Eigenvector => "Eigenvector",
+            CentralityMeasure::PageRank => "PageRank",
+        }
+    }
+}
+
+/// Each node's degree as a fraction of the most it could have
+pub fn degree(adjacent: &[Vec<usize>]) -> Vec<f32> {
+    let most = adjacent.len().saturating_sub(1).max(1) as f32;
+    adjacent.iter().map(|a| a.len() as f32 / most).collect()
+}
+
+/// Brandes' algorithm, normalized so that the centre of a star scores 1
+pub fn betweenness(adjacent: &[Vec<usize>]) -> Vec<f32> {
+    let n = adjacent.len();
+    let mut centrality = vec![0.; n];
+
+    for source in 0..n {
+        // count the shortest paths from the source to everything, in the order they're reached
+        let mut order = Vec::with_capacity(n);
+        let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); n];
+        let mut paths = vec![0f32; n];
+        let mut distance = vec![usize::MAX; n];
+        paths[source] = 1.;
+        distance[source] = 0;
+
+        let mut queue = VecDeque::from([source]);
+        while let Some(current) = queue.pop_front() {
+            order.push(current);
+            for &next in adjacent[current].iter() {
+                if distance[next] == usize::MAX {
+                    distance[next] = distance[current] + 1;
+                    queue.push_back(next);
+                }
+                if distance[next] == distance[current] + 1 {
+                    paths[next] += paths[current];
+                    predecessors[next].push(current);
+                }
+            }
+        }
+
+        // then hand each node's share of those paths back to its predecessors, furthest first
+        let mut dependency = vec![0f32; n];
+        for &current in order.iter().rev() {
+            for &previous in predecessors[current].iter() {
+                dependency[previous] += paths[previous] / paths[current] * (1. + dependency[current]);
+            }
+            if current != source {
+                centrality[current] += dependency[current];
+            }
+        }
+    }
+
+    // every pair was counted from both ends
+    let pairs = if n > 2 { ((n - 1) * (n - 2)) as f32 } else { 1. };
+    centrality.iter().map(|c| c / pairs).collect()
+}
+
+/// Wasserman and Faust's closeness, which stays comparable across disconnected components
+pub fn closeness(adjacent: &[Vec<usize>]) -> Vec<f32> {
+    let n = adjacent.len();
+    (0..n)
+        .map(|source| {
+            let mut distance = vec![usize::MAX; n];
+            distance[source] = 0;
+            let (mut reached, mut total) = (0, 0);
+            let mut queue = VecDeque::from([source]);
+            while let Some(current) = queue.pop_front() {
+                for &next in adjacent[current].iter() {
+                    if distance[next] == usize::MAX {
+                        distance[next] = distance[current] + 1;
+                        reached += 1;
+                        total += distance[next];
+                        queue.push_back(next);
+                    }
+                }
+            }
+            if total == 0 {
+                return 0.;
+            }
+            let reached = reached as f32;
+            reached / total as f32 * reached / (n - 1) as f32
+        })
+        .collect()
+}
+
+/// Repeats `step` from a uniform start until it settles
+fn power_iteration(n: usize, step: impl Fn(&[f32]) -> Vec<f32>) -> Vec<f32> {
+    let mut scores = vec![1. / n as f32; n];
+    for _ in 0..POWER_ITERATION_MAX_ROUNDS {
+        let next = step(&scores);
+        let change = next.iter().zip(scores.iter()).map(|(a, b)| (a - b).abs()).fold(0., f32::max);
+        scores = next;
+        if change < POWER_ITERATION_TOLERANCE {
+            break;
+        }
+    }
+    scores
+}
+
+/// The leading eigenvector of the adjacency matrix, scaled so the largest score is 1
+pub fn eigenvector(adjacent: &[Vec<usize>]) -> Vec<f32> {
+    power_iteration(adjacent.len(), |scores| {
+        // counting each node's own score as well stops bipartite graphs from oscillating
+        let next: Vec<f32> = adjacent.iter().enumerate()
+            .map(|(i, a)| scores[i] + a.iter().map(|&j| scores[j]).sum::<f32>())
+            .collect();
+        let max = next.iter().cloned().fold(0., f32::max);
+        if max > 0. { next.iter().map(|s| s / max).collect() } else { next }
+    })
+}
+
+/// PageRank, where a node with no neighbors jumps anywhere. The scores sum to 1
+pub fn pagerank(adjacent: &[Vec<usize>]) -> Vec<f32> {
+    let n = adjacent.len();
+    power_iteration(n, |scores| {
+        let dangling: f32 = adjacent.iter().zip(scores).filter(|(a, _)| a.is_empty()).map(|(_, s)| s).sum();
+        let base = (1. - PAGERANK_DAMPING + PAGERANK_DAMPING * dangling) / n as f32;
+        let mut next = vec![base; n];
+        for (i, a) in adjacent.iter().enumerate() {
+            for &j in a.iter() {
+                next[j] += PAGERANK_DAMPING * scores[i] / a.len() as f32;
+            }
+        }
+        next
+    })
+}
+
+/// Recomputes every Dot's `Centrality` whenever any Dot's `Neighbors` change or Dots are removed
+pub fn update_centrality(
+    mut commands: Commands,
+    dots_q: Query<(Entity, &Neighbors), With<Dot>>,
+    changed: Query<(), Changed<Neighbors>>,
+    mut removed: RemovedComponents<Dot>,
+) {
+    let removed = removed.read().count() > 0;
+    if changed.is_empty() && !removed {
+        return;
+    }
+
+    let adjacency = Adjacency::from_neighbors(dots_q.iter());
+    let adjacent = &adjacency.adjacent;
+    let (degree, betweenness, closeness) = (degree(adjacent), betweenness(adjacent), closeness(adjacent));
+    let (eigenvector, pagerank) = (eigenvector(adjacent), pagerank(adjacent));
+    for (i, eid) in adjacency.entities.iter().enumerate() {
+        commands.entity(*eid).insert(Centrality {
+            degree: degree[i],
+            betweenness: betweenness[i],
+            closeness: closeness[i],
+            eigenvector: eigenvector[i],
+            pagerank: pagerank[i],
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Undirected adjacency lists from a list of edges
+    fn graph(n: usize, edges: &[(usize, usize)]) -> Vec<Vec<usize>> {
+        let mut adjacent = vec![Vec::new(); n];
+        for &(a, b) in edges {
+            adjacent[a].push(b);
+            adjacent[b].push(a);
+        }
+        adjacent
+    }
+
+    /// Node 0 connected to every other node
+    fn star(n: usize) -> Vec<Vec<usize>> {
+        graph(n, &(1..n).map(|i| (0, i)).collect::<Vec<_>>())
+    }
+
+    fn path(n: usize) -> Vec<Vec<usize>> {
+        graph(n, &(1..n).map(|i| (i - 1, i)).collect::<Vec<_>>())
+    }
+
+    fn cycle(n: usize) -> Vec<Vec<usize>> {
+        graph(n, &(0..n).map(|i| (i, (i + 1) % n)).collect::<Vec<_>>())
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-4, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn betweenness_of_star_path_and_cycle() {
+        assert_close(&betweenness(&star(5)), &[1., 0., 0., 0., 0.]);
+        // the pairs on either side of a node pass through it, out of 4 * 3 / 2 = 6 pairs
+        assert_close(&betweenness(&path(5)), &[0., 3. / 6., 4. / 6., 3. / 6., 0.]);
+        // a node's two neighbors only connect through it, and the two pairs three apart which
+        // include a neighbor do half the time, out of 5 * 4 / 2 = 10 pairs
+        assert_close(&betweenness(&cycle(6)), &[(1. + 0.5 + 0.5) / 10.; 6]);
+    }
+
+    #[test]
+    fn closeness_of_star_path_and_cycle() {
+        assert_close(&closeness(&star(5)), &[1., 4. / 7., 4. / 7., 4. / 7., 4. / 7.]);
+        assert_close(&closeness(&path(5)), &[4. / 10., 4. / 7., 4. / 6., 4. / 7., 4. / 10.]);
+        assert_close(&closeness(&cycle(6)), &[5. / 9.; 6]);
+        // an isolated node reaches nothing, and the rest only count what they can reach
+        let mut adjacent = path(3);
+        adjacent.push(Vec::new());
+        assert_close(&closeness(&adjacent), &[2. / 3. * 2. / 3., 2. / 3., 2. / 3. * 2. / 3., 0.]);
+    }
+
+    #[test]
+    fn eigenvector_of_star_and_regular_graphs() {
+        // with 4 leaves the leading eigenvalue of A + I is 3, and each leaf scores half the hub
+        assert_close(&eigenvector(&star(5)), &[1., 0.5, 0.5, 0.5, 0.5]);
+        assert_close(&eigenvector(&cycle(6)), &[1.; 6]);
+        let complete: Vec<(usize, usize)> = (0..4).flat_map(|i| (i + 1..4).map(move |j| (i, j))).collect();
+        assert_close(&eigenvector(&graph(4, &complete)), &[1.; 4]);
+    }
+
+    #[test]
+    fn pagerank_of_star_path_and_cycle() {
+        let d = PAGERANK_DAMPING;
+        // the centre gets a share of every leaf's score, and the leaves split the centre's
+        let center = ((1. - d) / 5. + d) / (1. + d);
+        let leaf = (1. - center) / 4.;
+        assert_close(&pagerank(&star(5)), &[center, leaf, leaf, leaf, leaf]);
+
+        let scores = pagerank(&path(5));
+        assert!((scores.iter().sum::<f32>() - 1.).abs() < 1e-4);
+        assert_close(&scores, &[scores[4], scores[3], scores[2], scores[1], scores[0]]);
+        assert!(scores[0] < scores[1]);
+
+        assert_close(&pagerank(&cycle(6)), &[1. / 6.; 6]);
+        // isolated nodes are scored as if they linked to everything
+        assert_close(&pagerank(&graph(4, &[])), &[0.25; 4]);
+    }
+}
@@ -0,0 +1,439 @@
+use bevy::{prelude::*, utils::HashMap};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{graph::{Adjacency, Dot, Neighbors}, SEED};
+
+/// Higher values favour more, smaller communities
+const COMMUNITY_RESOLUTION: f32 = 1.;
+/// Gives up on label propagation if it hasn't settled after this many rounds
+const LABEL_PROPAGATION_MAX_ROUNDS: usize = 100;
+/// Gives up on moving nodes between communities if it hasn't settled after this many rounds
+const LOCAL_MOVING_MAX_ROUNDS: usize = 100;
+/// The smallest increase in modularity (scaled by the number of edges) worth moving a node for
+const MIN_GAIN: f32 = 1e-6;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CommunityMethod {
+    /// Greedy modularity optimisation, merging communities level by level
+    Louvain,
+    /// Louvain, but communities are refined into well connected parts which are merged instead,
+    /// so no community ends up disconnected
+    Leiden,
+    /// Every dot repeatedly takes the most common label among its neighbors
+    LabelPropagation,
+}
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommunityConfig {
+    pub method: CommunityMethod,
+    /// Used by Louvain and Leiden
+    pub resolution: f32,
+}
+
+impl Default for CommunityConfig {
+    fn default() -> Self {
+        CommunityConfig {
+            method: CommunityMethod::Louvain,
+            resolution: COMMUNITY_RESOLUTION,
+        }
+    }
+}
+
+/// Which community a Dot belongs to. Communities are numbered from largest to smallest
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Community(pub usize);
+
+/// The result of the last community detection
+#[derive(Resource, Debug, Default)]
+pub struct CommunityStats {
+    pub count: usize,
+    pub modularity: f32,
+}
+
+/// A weighted undirected graph, where `adjacent[i]` lists `(j, weight)`. A node may list itself,
+/// for the edges inside the community it stands for.
+type WeightedGraph = Vec<Vec<(usize, f32)>>;
+
+fn weighted(adjacent: &[Vec<usize>]) -> WeightedGraph {
+    adjacent.iter().map(|a| a.iter().map(|&j| (j, 1.)).collect()).collect()
+}
+
+fn degrees(graph: &WeightedGraph) -> Vec<f32> {
+    graph.iter().map(|a| a.iter().map(|(_, w)| w).sum()).collect()
+}
+
+/// Relabels so that the largest community is 0 and labels are consecutive
+fn relabel_by_size(labels: &[usize]) -> Vec<usize> {
+    let mut sizes: HashMap<usize, usize> = HashMap::new();
+    for &label in labels {
+        *sizes.entry(label).or_insert(0) += 1;
+    }
+    let mut order: Vec<usize> = sizes.keys().cloned().collect();
+    order.sort_by_key(|label| (std::cmp::Reverse(sizes[label]), *label));
+    let rank: HashMap<usize, usize> = order.iter().enumerate().map(|(r, &label)| (label, r)).collect();
+    labels.iter().map(|label| rank[label]).collect()
+}
+
+/// Newman's modularity of a partition of an unweighted graph
+pub fn modularity(adjacent: &[Vec<usize>], communities: &[usize], resolution: f32) -> f32 {
+    let two_m: f32 = adjacent.iter().map(|a| a.len() as f32).sum();
+    if two_m == 0. {
+        return 0.;
+    }
+
+    let count = communities.iter().max().map_or(0, |c| c + 1);
+    let mut inside = vec![0.; count];
+    let mut total = vec![0.; count];
+    for (i, a) in adjacent.iter().enumerate() {
+        total[communities[i]] += a.len() as f32;
+        inside[communities[i]] += a.iter().filter(|&&j| communities[j] == communities[i]).count() as f32;
+    }
+
+    inside.iter().zip(total.iter())
+        .map(|(inside, total)| inside / two_m - resolution * (total / two_m).powi(2))
+        .sum()
+}
+
+/// Starting from `community`, moves single nodes to whichever neighboring community increases
+/// modularity the most, until no move helps. Communities must be numbered below the number of
+/// nodes. Returns each node's community and whether anything moved.
+fn local_moving(graph: &WeightedGraph, resolution: f32, mut community: Vec<usize>) -> (Vec<usize>, bool) {
+    let n = graph.len();
+    let k = degrees(graph);
+    let two_m: f32 = k.iter().sum();
+    let mut total = vec![0.; n];
+    for i in 0..n {
+        total[community[i]] += k[i];
+    }
+    let mut moved_any = false;
+
+    for _ in 0..LOCAL_MOVING_MAX_ROUNDS {
+        let mut moved = false;
+        for i in 0..n {
+            let current = community[i];
+            total[current] -= k[i];
+
+            let mut links: HashMap<usize, f32> = HashMap::new();
+            for &(j, w) in graph[i].iter().filter(|(j, _)| *j != i) {
+                *links.entry(community[j]).or_insert(0.) += w;
+            }
+            let mut links: Vec<(usize, f32)> = links.into_iter().collect();
+            links.sort_by_key(|(c, _)| *c);
+
+            // only move for a real improvement, so rounding can't make nodes swap back and forth
+            let gain = |c: usize, link: f32| link - resolution * total[c] * k[i] / two_m;
+            let stay = links.iter().find(|(c, _)| *c == current).map_or(0., |(_, link)| *link);
+            let mut best = (current, gain(current, stay) + MIN_GAIN);
+            for &(c, link) in links.iter() {
+                let g = gain(c, link);
+                if g > best.1 {
+                    best = (c, g);
+                }
+            }
+
+            community[i] = best.0;
+            total[best.0] += k[i];
+            if best.0 != current {
+                moved = true;
+                moved_any = true;
+            }
+        }
+        if !moved {
+            break;
+        }
+    }
+
+    (community, moved_any)
+}
+
+/// Leiden's refinement phase. Within each community every node starts on its own, then nodes still
+/// on their own join whichever refined community in the same community increases modularity the
+/// most. Only nodes well connected to the rest of their community are merged, and a node only
+/// ever joins a neighbor, so every refined community is connected.
+fn refine(graph: &WeightedGraph, community: &[usize], resolution: f32) -> Vec<usize> {
+    let n = graph.len();
+    let k = degrees(graph);
+    let two_m: f32 = k.iter().sum();
+    let mut community_total = vec![0.; n];
+    for i in 0..n {
+        community_total[community[i]] += k[i];
+    }
+
+    let mut refined: Vec<usize> = (0..n).collect();
+    let mut total = k.clone();
+    let mut size = vec![1; n];
+    for i in 0..n {
+        if size[refined[i]] > 1 {
+            continue;
+        }
+
+        let c = community[i];
+        let mut links: HashMap<usize, f32> = HashMap::new();
+        for &(j, w) in graph[i].iter().filter(|(j, _)| *j != i && community[*j] == c) {
+            *links.entry(refined[j]).or_insert(0.) += w;
+        }
+        let inside: f32 = links.values().sum();
+        if inside < resolution * k[i] * (community_total[c] - k[i]) / two_m {
+            continue;
+        }
+        let mut links: Vec<(usize, f32)> = links.into_iter().collect();
+        links.sort_by_key(|(r, _)| *r);
+
+        let gain = |r: usize, link: f32| link - resolution * total[r] * k[i] / two_m;
+        let mut best = (refined[i], MIN_GAIN);
+        for &(r, link) in links.iter() {
+            let g = gain(r, link);
+            if g > best.1 {
+                best = (r, g);
+            }
+        }
+
+        if best.0 != refined[i] {
+            size[refined[i]] -= 1;
+            total[refined[i]] -= k[i];
+            refined[i] = best.0;
+            size[best.0] += 1;
+            total[best.0] += k[i];
+        }
+    }
+
+    relabel_by_size(&refined)
+}
+
+/// Collapses every community into a single node
+fn aggregate(graph: &WeightedGraph, community: &[usize]) -> WeightedGraph {
+    let count = community.iter().max().map_or(0, |c| c + 1);
+    let mut weights: Vec<HashMap<usize, f32>> = vec![HashMap::new(); count];
+    for (i, a) in graph.iter().enumerate() {
+        for &(j, w) in a.iter() {
+            *weights[community[i]].entry(community[j]).or_insert(0.) += w;
+        }
+    }
+    weights.into_iter()
+        .map(|w| {
+            let mut a: Vec<(usize, f32)> = w.into_iter().collect();
+            a.sort_by_key(|(j, _)| *j);
+            a
+        })
+        .collect()
+}
+
+/// Louvain community detection. With `refine`, this is the Leiden algorithm: each level's
+/// communities are refined, and the refined communities are aggregated into the next level's
+/// nodes, which start out in the community they were refined from.
+pub fn louvain(adjacent: &[Vec<usize>], resolution: f32, refine_communities: bool) -> Vec<usize> {
+    let mut graph = weighted(adjacent);
+    // which node of the current level every original node has been aggregated into
+    let mut membership: Vec<usize> = (0..adjacent.len()).collect();
+    let mut initial: Vec<usize> = (0..adjacent.len()).collect();
+
+    loop {
+        let (community, moved) = local_moving(&graph, resolution, initial);
+        let nodes = if refine_communities { refine(&graph, &community, resolution) } else { relabel_by_size(&community) };
+        let count = nodes.iter().max().map_or(0, |c| c + 1);
+        if !moved || count == graph.len() {
+            // aggregating wouldn't change anything
+            let communities: Vec<usize> = membership.iter().map(|&m| community[m]).collect();
+            return relabel_by_size(&communities);
+        }
+
+        let mut next = vec![0; count];
+        for (i, &node) in nodes.iter().enumerate() {
+            next[node] = community[i];
+        }
+        initial = relabel_by_size(&next);
+        for m in membership.iter_mut() {
+            *m = nodes[*m];
+        }
+        graph = aggregate(&graph, &nodes);
+    }
+}
+
+/// Every node starts with its own label, then in a random order repeatedly takes the label most of
+/// its neighbors have, until nothing changes
+pub fn label_propagation(adjacent: &[Vec<usize>], rng: &mut impl Rng) -> Vec<usize> {
+    let n = adjacent.len();
+    let mut labels: Vec<usize> = (0..n).collect();
+    let mut order: Vec<usize> = (0..n).collect();
+
+    for _ in 0..LABEL_PROPAGATION_MAX_ROUNDS {
+        order.shuffle(rng);
+        let mut changed = false;
+        for &i in order.iter() {
+            if adjacent[i].is_empty() {
+                continue;
+            }
+            let mut counts: HashMap<usize, usize> = HashMap::new();
+            for &j in adjacent[i].iter() {
+                *counts.entry(labels[j]).or_insert(0) += 1;
+            }
+            let max = counts.values().cloned().max().unwrap_or(0);
+            // keep the current label on a tie, so that it settles
+            if counts.get(&labels[i]) == Some(&max) {
+                continue;
+            }
+            let mut best: Vec<usize> = counts.iter().filter(|(_, &c)| c == max).map(|(&label, _)| label).collect();
+            best.sort();
+            labels[i] = *best.choose(rng).unwrap();
+            changed = true;
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    relabel_by_size(&labels)
+}
+
+/// Detects communities whenever the graph or the method changes, and gives every Dot a
+/// `Community`
+pub fn detect_communities(
+    mut commands: Commands,
+    dots_q: Query<(Entity, &Neighbors), With<Dot>>,
+    changed: Query<(), Changed<Neighbors>>,
+    mut removed: RemovedComponents<Dot>,
+    config: Res<CommunityConfig>,
+    mut stats: ResMut<CommunityStats>,
+) {
+    let removed = removed.read().count() > 0;
+    if changed.is_empty() && !removed && !config.is_changed() {
+        return;
+    }
+
+    let adjacency = Adjacency::from_neighbors(dots_q.iter());
+    let communities = match config.method {
+        CommunityMethod::Louvain => louvain(&adjacency.adjacent, config.resolution, false),
+        CommunityMethod::Leiden => louvain(&adjacency.adjacent, config.resolution, true),
+        // seeded every time, so the same graph always gets the same communities
+        CommunityMethod::LabelPropagation => label_propagation(&adjacency.adjacent, &mut StdRng::seed_from_u64(SEED)),
+    };
+
+    *stats = CommunityStats {
+        count: communities.iter().max().map_or(0, |c| c + 1),
+        modularity: modularity(&adjacency.adjacent, &communities, config.resolution),
+    };
+    for (eid, community) in adjacency.entities.iter().zip(communities) {
+        commands.entity(*eid).insert(Community(community));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Undirected adjacency lists from a list of edges
+    fn graph(n: usize, edges: &[(usize, usize)]) -> Vec<Vec<usize>> {
+        let mut adjacent = vec![Vec::new(); n];
+        for &(a, b) in edges {
+            adjacent[a].push(b);
+            adjacent[b].push(a);
+        }
+        adjacent
+    }
+
+    /// Two cliques of `size` nodes, 0.. and size.., joined by a single edge between 0 and size
+    fn bridged_cliques(size: usize) -> Vec<Vec<usize>> {
+        let mut edges = Vec::new();
+        for offset in [0, size] {
+            for a in 0..size {
+                for b in a + 1..size {
+                    edges.push((offset + a, offset + b));
+                }
+            }
+        }
+        edges.push((0, size));
+        graph(size * 2, &edges)
+    }
+
+    fn assert_splits_cliques(communities: &[usize], size: usize) {
+        assert_eq!(communities.iter().max(), Some(&1));
+        assert!(communities[..size].iter().all(|&c| c == communities[0]));
+        assert!(communities[size..].iter().all(|&c| c == communities[size]));
+        assert_ne!(communities[0], communities[size]);
+    }
+
+    /// Whether every community's nodes are connected through nodes of the same community
+    fn connected(adjacent: &[Vec<usize>], communities: &[usize]) -> bool {
+        let mut seen = vec![false; adjacent.len()];
+        let mut parts = 0;
+        for start in 0..adjacent.len() {
+            if seen[start] {
+                continue;
+            }
+            parts += 1;
+            seen[start] = true;
+            let mut stack = vec![start];
+            while let Some(current) = stack.pop() {
+                for &j in adjacent[current].iter() {
+                    if !seen[j] && communities[j] == communities[start] {
+                        seen[j] = true;
+                        stack.push(j);
+                    }
+                }
+            }
+        }
+        parts == communities.iter().max().map_or(0, |c| c + 1)
+    }
+
+    #[test]
+    fn modularity_of_bridged_cliques() {
+        let adjacent = bridged_cliques(4);
+        // 13 edges, 6 inside each clique, and each clique's degrees add up to 13
+        let split: Vec<usize> = (0..8).map(|i| i / 4).collect();
+        let expected = 2. * (6. / 13. - (13f32 / 26.).powi(2));
+        assert!((modularity(&adjacent, &split, 1.) - expected).abs() < 1e-6);
+        assert!(modularity(&adjacent, &[0; 8], 1.).abs() < 1e-6);
+        assert!(modularity(&adjacent, &split, 1.) > modularity(&adjacent, &(0..8).collect::<Vec<_>>(), 1.));
+        assert_eq!(modularity(&graph(3, &[]), &[0, 1, 2], 1.), 0.);
+    }
+
+    #[test]
+    fn louvain_splits_bridged_cliques() {
+        assert_splits_cliques(&louvain(&bridged_cliques(5), 1., false), 5);
+    }
+
+    #[test]
+    fn leiden_splits_bridged_cliques() {
+        assert_splits_cliques(&louvain(&bridged_cliques(5), 1., true), 5);
+    }
+
+    #[test]
+    fn label_propagation_splits_bridged_cliques() {
+        for seed in 0..10 {
+            assert_splits_cliques(&label_propagation(&bridged_cliques(5), &mut StdRng::seed_from_u64(seed)), 5);
+        }
+    }
+
+    #[test]
+    fn louvain_is_at_least_as_good_as_each_node_alone() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..20 {
+            let n = 40;
+            let edges: Vec<(usize, usize)> = (0..80)
+                .map(|_| (rng.gen_range(0..n), rng.gen_range(0..n)))
+                .filter(|(a, b)| a != b)
+                .collect();
+            let adjacent = graph(n, &edges);
+            let alone: Vec<usize> = (0..n).collect();
+            for refine in [false, true] {
+                let communities = louvain(&adjacent, 1., refine);
+                assert!(modularity(&adjacent, &communities, 1.) >= modularity(&adjacent, &alone, 1.));
+            }
+        }
+    }
+
+    #[test]
+    fn leiden_communities_are_connected() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..50 {
+            let n = 60;
+            let edges: Vec<(usize, usize)> = (0..90)
+                .map(|_| (rng.gen_range(0..n), rng.gen_range(0..n)))
+                .filter(|(a, b)| a != b)
+                .collect();
+            let adjacent = graph(n, &edges);
+            assert!(connected(&adjacent, &louvain(&adjacent, 1., true)));
+        }
+    }
+}
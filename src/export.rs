@@ -0,0 +1,245 @@
+use std::fmt::Write;
+
+use bevy::{prelude::*, render::view::screenshot::ScreenshotManager, utils::HashMap, window::PrimaryWindow};
+use image::{Rgba, RgbaImage};
+
+use crate::{graph::{Dot, Edge, EdgeMode, GraphSpawnConfig, Partner}, phases::Phases, render::{edge_color, push_edge_lines, RenderConfig}, style::{DotStyle, EdgeStyle, DOT_RADIUS}, MainCamera, WIN_SIZE};
+
+pub const EXPORT_SVG_PATH: &str = "graph.svg";
+pub const EXPORT_PNG_PATH: &str = "graph.png";
+pub const EXPORT_SCREENSHOT_PATH: &str = "screenshot.png";
+
+/// Paths to write the current frame to, filled in by the UI and handled by `export_requested`
+#[derive(Resource, Default)]
+pub struct ExportRequests {
+    pub svg: Option<String>,
+    /// Captured from the window, so it includes everything on screen
+    pub screenshot: Option<String>,
+    /// Rasterised in software from the same data as the SVG
+    pub png: Option<String>,
+}
+
+/// Everything needed to draw a frame, in world coordinates
+pub struct ExportScene {
+    /// The part of the world that is visible
+    pub view: Rect,
+    pub background: Color,
+    /// Drawn in order, before the dots
+    pub lines: Vec<(Vec2, Vec2, Color)>,
+    /// Center, radius and colour
+    pub dots: Vec<(Vec2, f32, Color)>,
+}
+
+impl ExportScene {
+    /// Collects what the renderer would currently draw: dots in the dot phases, edges in the graph
+    /// phase and partners in the disconnected edges phase, as seen by the main camera if there is
+    /// one
+    pub fn from_world(world: &mut World) -> Self {
+        let phase = world.get_resource::<State<Phases>>().map_or(Phases::Init, |state| *state.get());
+
+        let window_size = Vec2::new(WIN_SIZE.0, WIN_SIZE.1);
+        let view = world.query_filtered::<(&Transform, &OrthographicProjection), With<MainCamera>>()
+            .iter(world)
+            .next()
+            .map(|(tf, projection)| Rect::from_center_size(tf.translation.xy(), window_size * projection.scale))
+            .unwrap_or(Rect::from_corners(Vec2::ZERO, window_size));
+        let background = world.get_resource::<ClearColor>().map_or(ClearColor::default().0, |c| c.0);
+
+        let mut dots_q = world.query_filtered::<(Entity, &Transform, Option<&DotStyle>, &Partner), With<Dot>>();
+        // position and radius of every dot
+        let dot_positions: HashMap<Entity, (Vec2, f32)> = dots_q.iter(world)
+            .map(|(eid, tf, style, _)| (eid, (tf.translation.xy(), style.map_or(DOT_RADIUS, |s| s.radius))))
+            .collect();
+
+        let mut lines = Vec::new();
+        match phase {
+            Phases::Graph => {
+                let render_config = world.get_resource::<RenderConfig>().cloned().unwrap_or_default();
+                let edge_mode = world.get_resource::<GraphSpawnConfig>().map_or(EdgeMode::Undirected, |c| c.edge_mode.clone());
+                let mut edges_q = world.query::<(&Edge, &EdgeStyle)>();
+                let max_weight = edges_q.iter(world).map(|(edge, _)| edge.weight).fold(0., f32::max);
+                for (edge, style) in edges_q.iter(world) {
+                    let (Some(&(pos, _)), Some(&(target_pos, target_radius))) = (dot_positions.get(&edge.source), dot_positions.get(&edge.target)) else { continue };
+                    let color = edge_color(edge, style, max_weight, &render_config);
+                    push_edge_lines(&mut lines, pos, target_pos, target_radius, color, &edge_mode);
+                }
+            }
+            Phases::DisconnectedEdges => {
+                for (eid, _, _, partner) in dots_q.iter(world) {
+                    let Some(partner_eid) = partner.partner else { continue };
+                    if let (Some(a), Some(b)) = (dot_positions.get(&eid), dot_positions.get(&partner_eid)) {
+                        lines.push((a.0, b.0, Color::WHITE));
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        let dots = if matches!(phase, Phases::JustDots | Phases::Graph) {
+            dots_q.iter(world)
+                .map(|(_, tf, style, _)| {
+                    let style = style.cloned().unwrap_or_default();
+                    (tf.translation.xy(), style.radius, style.color)
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        ExportScene { view, background, lines, dots }
+    }
+
+    pub fn to_svg(&self) -> String {
+        let mut svg = String::new();
+        let size = self.view.size();
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+            WIN_SIZE.0, WIN_SIZE.1, self.view.min.x, self.view.min.y, size.x, size.y,
+        ).unwrap();
+        writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+            self.view.min.x, self.view.min.y, size.x, size.y, svg_color(self.background),
+        ).unwrap();
+
+        writeln!(svg, r#"<g stroke-width="1" stroke-linecap="round">"#).unwrap();
+        for (a, b, color) in self.lines.iter() {
+            writeln!(
+                svg,
+                r#"<line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}" stroke="{}" stroke-opacity="{:.3}"/>"#,
+                a.x, a.y, b.x, b.y, svg_color(*color), color.a(),
+            ).unwrap();
+        }
+        writeln!(svg, "</g>").unwrap();
+
+        writeln!(svg, "<g>").unwrap();
+        for (center, radius, color) in self.dots.iter() {
+            writeln!(
+                svg,
+                r#"<circle cx="{:.2}" cy="{:.2}" r="{:.2}" fill="{}" fill-opacity="{:.3}"/>"#,
+                center.x, center.y, radius, svg_color(*color), color.a(),
+            ).unwrap();
+        }
+        writeln!(svg, "</g>").unwrap();
+
+        writeln!(svg, "</svg>").unwrap();
+        svg
+    }
+
+    /// Draws the scene with a small anti-aliased software rasteriser, so that no GPU or window is
+    /// needed
+    pub fn rasterize(&self, width: u32, height: u32) -> RgbaImage {
+        let mut image = RgbaImage::from_pixel(width, height, Rgba(self.background.as_rgba_u8()));
+        let scale = Vec2::new(width as f32, height as f32) / self.view.size();
+        let to_pixel = |p: Vec2| (p - self.view.min) * scale;
+
+        for (a, b, color) in self.lines.iter() {
+            draw_line(&mut image, to_pixel(*a), to_pixel(*b), *color);
+        }
+        for (center, radius, color) in self.dots.iter() {
+            draw_circle(&mut image, to_pixel(*center), radius * scale.x, *color);
+        }
+
+        image
+    }
+}
+
+fn svg_color(color: Color) -> String {
+    let [r, g, b, _] = color.as_rgba_u8();
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Alpha blends `color` onto a pixel, scaled by how much of the pixel is covered
+fn blend(image: &mut RgbaImage, x: i64, y: i64, color: Color, coverage: f32) {
+    if x < 0 || y < 0 || x >= image.width() as i64 || y >= image.height() as i64 {
+        return;
+    }
+    let alpha = (color.a() * coverage).clamp(0., 1.);
+    let pixel = image.get_pixel_mut(x as u32, y as u32);
+    let source = [color.r(), color.g(), color.b()];
+    for (channel, source) in pixel.0.iter_mut().zip(source) {
+        *channel = (*channel as f32 * (1. - alpha) + source * 255. * alpha).round() as u8;
+    }
+}
+
+/// Xiaolin Wu's anti-aliased line algorithm
+fn draw_line(image: &mut RgbaImage, mut a: Vec2, mut b: Vec2, color: Color) {
+    let steep = (b.y - a.y).abs() > (b.x - a.x).abs();
+    if steep {
+        a = a.yx();
+        b = b.yx();
+    }
+    if a.x > b.x {
+        std::mem::swap(&mut a, &mut b);
+    }
+
+    let gradient = if b.x == a.x { 1. } else { (b.y - a.y) / (b.x - a.x) };
+    let mut plot = |x: i64, y: i64, coverage: f32| {
+        if steep { blend(image, y, x, color, coverage) } else { blend(image, x, y, color, coverage) }
+    };
+
+    let mut y = a.y + gradient * (a.x.round() - a.x);
+    for x in a.x.round() as i64..=b.x.round() as i64 {
+        let fract = y - y.floor();
+        plot(x, y.floor() as i64, 1. - fract);
+        plot(x, y.floor() as i64 + 1, fract);
+        y += gradient;
+    }
+}
+
+fn draw_circle(image: &mut RgbaImage, center: Vec2, radius: f32, color: Color) {
+    let min = (center - radius - 1.).floor();
+    let max = (center + radius + 1.).ceil();
+    for y in min.y as i64..=max.y as i64 {
+        for x in min.x as i64..=max.x as i64 {
+            let distance = Vec2::new(x as f32 + 0.5, y as f32 + 0.5).distance(center);
+            let coverage = (radius + 0.5 - distance).clamp(0., 1.);
+            if coverage > 0. {
+                blend(image, x, y, color, coverage);
+            }
+        }
+    }
+}
+
+pub fn write_svg(scene: &ExportScene, path: &str) -> Result<(), String> {
+    std::fs::write(path, scene.to_svg()).map_err(|e| e.to_string())
+}
+
+pub fn write_png(scene: &ExportScene, path: &str) -> Result<(), String> {
+    scene.rasterize(WIN_SIZE.0 as u32, WIN_SIZE.1 as u32).save(path).map_err(|e| e.to_string())
+}
+
+/// Handles any exports requested from the UI
+pub fn export_requested(world: &mut World) {
+    let Some(mut requests) = world.get_resource_mut::<ExportRequests>() else { return };
+    let (svg, screenshot, png) = (requests.svg.take(), requests.screenshot.take(), requests.png.take());
+
+    if svg.is_some() || png.is_some() {
+        let scene = ExportScene::from_world(world);
+        if let Some(path) = svg {
+            match write_svg(&scene, &path) {
+                Ok(()) => info!("exported SVG to {}", path),
+                Err(e) => warn!("could not export SVG to {}: {}", path, e),
+            }
+        }
+        if let Some(path) = png {
+            match write_png(&scene, &path) {
+                Ok(()) => info!("exported PNG to {}", path),
+                Err(e) => warn!("could not export PNG to {}: {}", path, e),
+            }
+        }
+    }
+
+    if let Some(path) = screenshot {
+        let window = world.query_filtered::<Entity, With<PrimaryWindow>>().iter(world).next();
+        match (window, world.get_resource_mut::<ScreenshotManager>()) {
+            (Some(window), Some(mut screenshot_manager)) => {
+                if let Err(e) = screenshot_manager.save_screenshot_to_disk(window, &path) {
+                    warn!("could not take screenshot: {}", e);
+                }
+            }
+            _ => warn!("can't take a screenshot without a window"),
+        }
+    }
+}
@@ -0,0 +1,408 @@
+use bevy::{math::DVec2, prelude::*, utils::HashSet};
+
+/// How far points are nudged before triangulating, as a fraction of the size of the point set
+const DELAUNAY_JITTER: f64 = 1e-7;
+
+struct Triangle {
+    vertices: [usize; 3],
+    circumcenter: DVec2,
+    circumradius_sq: f64,
+}
+
+impl Triangle {
+    fn new(vertices: [usize; 3], points: &[DVec2]) -> Self {
+        let [a, b, c] = vertices.map(|v| points[v]);
+        let d = 2. * (a.x * (b.y - c.y) + b.x * (c.y - a.y) + c.x * (a.y - b.y));
+        let circumcenter = if d == 0. {
+            // degenerate (collinear) triangle: make its circumcircle contain everything so it gets
+            // replaced as soon as possible
+            DVec2::ZERO
+        } else {
+            DVec2::new(
+                (a.length_squared() * (b.y - c.y) + b.length_squared() * (c.y - a.y) + c.length_squared() * (a.y - b.y)) / d,
+                (a.length_squared() * (c.x - b.x) + b.length_squared() * (a.x - c.x) + c.length_squared() * (b.x - a.x)) / d,
+            )
+        };
+        let circumradius_sq = if d == 0. { f64::INFINITY } else { circumcenter.distance_squared(a) };
+
+        Triangle { vertices, circumcenter, circumradius_sq }
+    }
+
+    fn edges(&self) -> [(usize, usize); 3] {
+        let [a, b, c] = self.vertices;
+        [ordered(a, b), ordered(b, c), ordered(c, a)]
+    }
+}
+
+fn ordered(a: usize, b: usize) -> (usize, usize) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+/// A small offset for the `i`th point, evenly spread over the unit square centered on the origin
+fn jitter(i: usize) -> DVec2 {
+    // the R2 low-discrepancy sequence
+    const A1: f64 = 0.754_877_666_246_692_7;
+    const A2: f64 = 0.569_840_290_998_053_2;
+    DVec2::new((i as f64 * A1).fract(), (i as f64 * A2).fract()) - 0.5
+}
+
+/// Computes the edges of the Delaunay triangulation of `points` using the Bowyer-Watson
+/// algorithm. Each edge is returned once, as `(i, j)` with `i < j`. Points which exactly coincide
+/// with an earlier point are left unconnected.
+pub fn delaunay_edges(points: &[Vec2]) -> Vec<(usize, usize)> {
+    let n = points.len();
+    if n < 2 {
+        return Vec::new();
+    }
+
+    // work in f64 since the circumcircle test is sensitive to rounding
+    let mut vertices: Vec<DVec2> = points.iter().map(|p| p.as_dvec2()).collect();
+
+    // a triangle large enough to contain every point, which is removed at the end
+    let min = vertices.iter().cloned().fold(DVec2::splat(f64::INFINITY), DVec2::min);
+    let max = vertices.iter().cloned().fold(DVec2::splat(f64::NEG_INFINITY), DVec2::max);
+    let center = (min + max) / 2.;
+    let extent = (max - min).max_element().max(1.) * 20.;
+
+    // lattice spawns put many points on one line or one circle, where the circumcircle test is
+    // decided by rounding and the hole left by the bad triangles may not be star-shaped. Nudging
+    // every point by a tiny, fixed amount breaks those ties consistently
+    for (i, v) in vertices.iter_mut().enumerate() {
+        *v += jitter(i) * extent * DELAUNAY_JITTER;
+    }
+    vertices.push(center + DVec2::new(-extent, -extent));
+    vertices.push(center + DVec2::new(extent, -extent));
+    vertices.push(center + DVec2::new(0., extent));
+
+    let mut triangles = vec![Triangle::new([n, n + 1, n + 2], &vertices)];
+    let mut seen = HashSet::new();
+
+    for i in 0..n {
+        if !seen.insert((points[i].x.to_bits(), points[i].y.to_bits())) {
+            continue;
+        }
+        let p = vertices[i];
+
+        let (bad, good): (Vec<Triangle>, Vec<Triangle>) = triangles.into_iter()
+            .partition(|t| t.circumcenter.distance_squared(p) < t.circumradius_sq);
+        triangles = good;
+
+        // the boundary of the hole is made of the edges that belong to exactly one bad triangle
+        let mut edge_counts: Vec<((usize, usize), usize)> = Vec::new();
+        for edge in bad.iter().flat_map(|t| t.edges()) {
+            match edge_counts.iter_mut().find(|(e, _)| *e == edge) {
+                Some((_, count)) => *count += 1,
+                None => edge_counts.push((edge, 1)),
+            }
+        }
+
+        for ((a, b), _) in edge_counts.into_iter().filter(|(_, count)| *count == 1) {
+            triangles.push(Triangle::new([a, b, i], &vertices));
+        }
+    }
+
+    let mut edges: Vec<(usize, usize)> = triangles.iter()
+        .filter(|t| t.vertices.iter().all(|&v| v < n))
+        .flat_map(|t| t.edges())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    edges.sort();
+    edges
+}
+
+/// Whether `r` is strictly inside the lune-based β-skeleton region of the segment `pq`.
+/// For β = 1 the region is the disk with `pq` as its diameter (Gabriel graph), and for β = 2 it is
+/// the lune of the relative neighborhood graph.
+pub fn in_beta_lune(p: Vec2, q: Vec2, r: Vec2, beta: f32) -> bool {
+    let d = p.distance(q);
+    if beta >= 1. {
+        // intersection of two disks of radius βd/2 centered along pq
+        let radius = beta * d / 2.;
+        let c1 = p.lerp(q, beta / 2.);
+        let c2 = q.lerp(p, beta / 2.);
+        r.distance(c1) < radius && r.distance(c2) < radius
+    } else {
+        // intersection of two disks of radius d/2β whose boundaries pass through p and q
+        let radius = d / (2. * beta);
+        let midpoint = (p + q) / 2.;
+        let normal = (q - p).perp().normalize_or_zero();
+        let offset = (radius * radius - d * d / 4.).max(0.).sqrt();
+        let c1 = midpoint + normal * offset;
+        let c2 = midpoint - normal * offset;
+        r.distance(c1) < radius && r.distance(c2) < radius
+    }
+}
+
+/// Points bucketed into square cells, so the points near somewhere can be found without checking
+/// every one of them
+struct PointGrid {
+    min: Vec2,
+    cell_size: f32,
+    width: i32,
+    height: i32,
+    cells: Vec<Vec<usize>>,
+}
+
+impl PointGrid {
+    fn new(points: &[Vec2]) -> Self {
+        let min = points.iter().cloned().fold(Vec2::splat(f32::INFINITY), Vec2::min);
+        let max = points.iter().cloned().fold(Vec2::splat(f32::NEG_INFINITY), Vec2::max);
+        // about one point per cell
+        let cell_size = ((max - min).max_element() / (points.len() as f32).sqrt()).max(f32::EPSILON);
+        let width = ((max.x - min.x) / cell_size) as i32 + 1;
+        let height = ((max.y - min.y) / cell_size) as i32 + 1;
+
+        let mut grid = PointGrid { min, cell_size, width, height, cells: vec![Vec::new(); (width * height) as usize] };
+        for (i, &p) in points.iter().enumerate() {
+            let cell = grid.cell(p);
+            grid.cells[(cell.y * width + cell.x) as usize].push(i);
+        }
+        grid
+    }
+
+    fn cell(&self, p: Vec2) -> IVec2 {
+        ((p - self.min) / self.cell_size).floor().as_ivec2().clamp(IVec2::ZERO, IVec2::new(self.width - 1, self.height - 1))
+    }
+
+    /// Whether any point within `radius` of `center` satisfies `f`, checking the nearest cells
+    /// first. Points a little further away may be checked too
+    fn any_near(&self, center: Vec2, radius: f32, mut f: impl FnMut(usize) -> bool) -> bool {
+        let c = self.cell(center);
+        let rings = ((radius / self.cell_size).ceil() as i32 + 1).min(self.width.max(self.height));
+        let mut check = |x: i32, y: i32| {
+            x >= 0 && y >= 0 && x < self.width && y < self.height
+                && self.cells[(y * self.width + x) as usize].iter().any(|&k| f(k))
+        };
+        if check(c.x, c.y) {
+            return true;
+        }
+        for ring in 1..=rings {
+            // the square of cells `ring` away from the centre's cell, one side at a time
+            for d in -ring..ring {
+                if check(c.x + d, c.y - ring) || check(c.x + ring, c.y + d) || check(c.x - d, c.y + ring) || check(c.x - ring, c.y - d) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Keeps the candidate edges whose β-lune contains no other point
+pub fn beta_skeleton_edges(points: &[Vec2], candidates: &[(usize, usize)], beta: f32) -> Vec<(usize, usize)> {
+    if points.is_empty() {
+        return Vec::new();
+    }
+
+    let grid = PointGrid::new(points);
+    candidates.iter()
+        .cloned()
+        .filter(|&(i, j)| {
+            let (p, q) = (points[i], points[j]);
+            // the lune is no longer than pq, and no wider than this
+            let radius = p.distance(q) / 2. * (2. * beta - 1.).max(1.).sqrt();
+            !grid.any_near((p + q) / 2., radius, |k| k != i && k != j && in_beta_lune(p, q, points[k], beta))
+        })
+        .collect()
+}
+
+/// Computes the Euclidean minimum spanning tree (forest, if there are coincident points) with
+/// Kruskal's algorithm. The EMST is a subgraph of the Delaunay triangulation, so only its edges
+/// need to be considered.
+pub fn minimum_spanning_tree_edges(points: &[Vec2], delaunay: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut sorted = delaunay.to_vec();
+    sorted.sort_by(|&(a, b), &(c, d)| {
+        points[a].distance_squared(points[b]).total_cmp(&points[c].distance_squared(points[d]))
+    });
+
+    let mut parent: Vec<usize> = (0..points.len()).collect();
+    fn find(parent: &mut [usize], mut x: usize) -> usize {
+        while parent[x] != x {
+            parent[x] = parent[parent[x]];
+            x = parent[x];
+        }
+        x
+    }
+
+    let mut edges = Vec::new();
+    for (i, j) in sorted {
+        let (root_i, root_j) = (find(&mut parent, i), find(&mut parent, j));
+        if root_i != root_j {
+            parent[root_i] = root_j;
+            edges.push((i, j));
+        }
+    }
+    edges
+}
+
+/// The point on segment `a`-`b` closest to `p`
+pub fn closest_point_on_segment(p: Vec2, a: Vec2, b: Vec2) -> Vec2 {
+    let ab = b - a;
+    let t = if ab.length_squared() > 0. { ((p - a).dot(ab) / ab.length_squared()).clamp(0., 1.) } else { 0. };
+    a + ab * t
+}
+
+/// Whether `p` is inside the polygon with these vertices, by the even-odd rule
+pub fn point_in_polygon(p: Vec2, vertices: &[Vec2]) -> bool {
+    let mut inside = false;
+    for (i, &a) in vertices.iter().enumerate() {
+        let b = vertices[(i + 1) % vertices.len()];
+        if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// The point on the outline of the polygon with these vertices closest to `p`
+pub fn closest_point_on_polygon(p: Vec2, vertices: &[Vec2]) -> Option<Vec2> {
+    (0..vertices.len())
+        .map(|i| closest_point_on_segment(p, vertices[i], vertices[(i + 1) % vertices.len()]))
+        .min_by(|a, b| a.distance_squared(p).total_cmp(&b.distance_squared(p)))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    fn random_points(n: usize, seed: u64) -> Vec<Vec2> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n).map(|_| Vec2::new(rng.gen_range(0.0..1000.0), rng.gen_range(0.0..600.0))).collect()
+    }
+
+    fn lattice(columns: usize, rows: usize, spacing: f32) -> Vec<Vec2> {
+        (0..rows).flat_map(|y| (0..columns).map(move |x| Vec2::new(x as f32, y as f32) * spacing)).collect()
+    }
+
+    /// Whether segments `a` and `b` cross at a point inside both of them
+    fn crosses(a: (Vec2, Vec2), b: (Vec2, Vec2)) -> bool {
+        let side = |p: Vec2, q: Vec2, r: Vec2| (q - p).perp_dot(r - p);
+        side(b.0, b.1, a.0) * side(b.0, b.1, a.1) < 0. && side(a.0, a.1, b.0) * side(a.0, a.1, b.1) < 0.
+    }
+
+    fn assert_planar(points: &[Vec2], edges: &[(usize, usize)]) {
+        for (k, &(a, b)) in edges.iter().enumerate() {
+            for &(c, d) in edges[k + 1..].iter() {
+                assert!(!crosses((points[a], points[b]), (points[c], points[d])), "{:?} crosses {:?}", (a, b), (c, d));
+            }
+        }
+    }
+
+    fn total_length(points: &[Vec2], edges: &[(usize, usize)]) -> f32 {
+        edges.iter().map(|&(i, j)| points[i].distance(points[j])).sum()
+    }
+
+    fn is_subset(a: &[(usize, usize)], b: &[(usize, usize)]) -> bool {
+        let b: HashSet<_> = b.iter().collect();
+        a.iter().all(|edge| b.contains(edge))
+    }
+
+    #[test]
+    fn delaunay_of_a_square_has_its_sides_and_one_diagonal() {
+        let points = lattice(2, 2, 10.);
+        let edges = delaunay_edges(&points);
+        assert_eq!(edges.len(), 5);
+        for side in [(0, 1), (0, 2), (1, 3), (2, 3)] {
+            assert!(edges.contains(&side));
+        }
+    }
+
+    #[test]
+    fn delaunay_of_random_points_is_a_planar_triangulation() {
+        let points = random_points(200, 1);
+        let edges = delaunay_edges(&points);
+        assert_planar(&points, &edges);
+        // Euler's formula for a triangulation has 3n - 3 - h edges, with h points on the hull
+        assert!(edges.len() > 2 * points.len() && edges.len() <= 3 * points.len() - 6);
+    }
+
+    #[test]
+    fn delaunay_of_a_lattice_is_planar_and_keeps_every_side() {
+        let points = lattice(12, 10, 40.);
+        let edges = delaunay_edges(&points);
+        assert_planar(&points, &edges);
+        for (i, &p) in points.iter().enumerate() {
+            for (j, &q) in points.iter().enumerate().skip(i + 1) {
+                if p.distance(q) < 40.5 {
+                    assert!(edges.contains(&(i, j)), "missing lattice side {:?}", (i, j));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn delaunay_of_mostly_collinear_points_is_planar() {
+        let mut points: Vec<Vec2> = (0..20).map(|i| Vec2::new(i as f32 * 10., 0.)).collect();
+        points.extend([Vec2::new(95., 7.), Vec2::new(35., -9.)]);
+        let edges = delaunay_edges(&points);
+        assert_planar(&points, &edges);
+        // 3n - 3 - h, with the two ends of the line and the two points off it on the hull
+        assert_eq!(edges.len(), 3 * points.len() - 3 - 4);
+    }
+
+    #[test]
+    fn delaunay_leaves_coincident_points_unconnected() {
+        let points = vec![Vec2::ZERO, Vec2::X * 10., Vec2::Y * 10., Vec2::ZERO];
+        let edges = delaunay_edges(&points);
+        assert_eq!(edges, vec![(0, 1), (0, 2), (1, 2)]);
+    }
+
+    #[test]
+    fn gabriel_drops_edges_with_a_point_in_their_diameter_circle() {
+        let points = vec![Vec2::ZERO, Vec2::new(2., 0.), Vec2::new(1., 0.5)];
+        let edges = beta_skeleton_edges(&points, &delaunay_edges(&points), 1.);
+        assert_eq!(edges, vec![(0, 2), (1, 2)]);
+    }
+
+    #[test]
+    fn skeletons_nest_inside_each_other() {
+        let points = random_points(150, 2);
+        let delaunay = delaunay_edges(&points);
+        let gabriel = beta_skeleton_edges(&points, &delaunay, 1.);
+        let relative_neighborhood = beta_skeleton_edges(&points, &delaunay, 2.);
+        let spanning_tree = minimum_spanning_tree_edges(&points, &delaunay);
+        assert!(is_subset(&gabriel, &delaunay));
+        assert!(is_subset(&relative_neighborhood, &gabriel));
+        assert!(is_subset(&spanning_tree, &relative_neighborhood));
+    }
+
+    #[test]
+    fn beta_skeleton_matches_checking_every_point() {
+        let points = random_points(80, 3);
+        let all_pairs: Vec<(usize, usize)> = (0..points.len())
+            .flat_map(|i| (i + 1..points.len()).map(move |j| (i, j)))
+            .collect();
+        for beta in [0.5, 0.9, 1., 1.5, 2., 3.] {
+            let expected: Vec<(usize, usize)> = all_pairs.iter()
+                .cloned()
+                .filter(|&(i, j)| !(0..points.len()).any(|k| k != i && k != j && in_beta_lune(points[i], points[j], points[k], beta)))
+                .collect();
+            assert_eq!(beta_skeleton_edges(&points, &all_pairs, beta), expected, "β = {}", beta);
+        }
+    }
+
+    #[test]
+    fn minimum_spanning_tree_matches_prim() {
+        let points = random_points(100, 4);
+        let tree = minimum_spanning_tree_edges(&points, &delaunay_edges(&points));
+        assert_eq!(tree.len(), points.len() - 1);
+
+        // Prim's algorithm over every pair
+        let mut in_tree = vec![false; points.len()];
+        let mut distance = vec![f32::INFINITY; points.len()];
+        distance[0] = 0.;
+        let mut expected = 0.;
+        for _ in 0..points.len() {
+            let next = (0..points.len()).filter(|&i| !in_tree[i]).min_by(|&a, &b| distance[a].total_cmp(&distance[b])).unwrap();
+            in_tree[next] = true;
+            expected += distance[next];
+            for i in 0..points.len() {
+                distance[i] = distance[i].min(points[i].distance(points[next]));
+            }
+        }
+        assert!((total_length(&points, &tree) - expected).abs() < 1e-2);
+    }
+}
@@ -1,24 +1,69 @@
-use std::{cmp::Ordering, collections::BinaryHeap};
+use std::{cmp::Ordering, collections::{BinaryHeap, VecDeque}, fs};
 
 use bevy::{prelude::*, utils::{HashMap, HashSet}};
 use ordered_float::NotNan;
+use serde::{Deserialize, Serialize};
+
+use crate::{geometry::{beta_skeleton_edges, delaunay_edges, minimum_spanning_tree_edges}, style::EdgeStyle};
 
 /// How close two dots have to be to be considered neighbors when using distance
 const COMPUTE_NEIGHBORS_MAX_DISTANCE: f32 = 80.0;
 /// The number of connections to compute for each dot when using K-nearest neighbors
 const COMPUTE_NEIGHBORS_K_NEAREST: usize = 5;
+/// The shape of the empty region required around each edge when using β-skeletons
+const COMPUTE_NEIGHBORS_BETA: f32 = 1.5;
+/// A list of `(source, target, weight)` for edges between Dots, given by the order they're spawned
+/// in
+const EDGE_WEIGHTS_PATH: &str = "edge_weights.ron";
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ComputeNeighborsMethod {
     Distance,
     KNearest,
+    Delaunay,
+    /// Delaunay edges whose diametral disk contains no other dot
+    Gabriel,
+    /// Edges with no other dot closer to both endpoints than they are to each other
+    RelativeNeighborhood,
+    /// Euclidean minimum spanning tree
+    MinimumSpanningTree,
+    /// Lune-based β-skeleton, which is the Gabriel graph at β = 1 and the relative neighborhood
+    /// graph at β = 2
+    BetaSkeleton,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EdgeMode {
+    /// One edge per connected pair, however many sides list the other in their `Neighbors`
+    Undirected,
+    /// One edge per entry in every `Neighbors`, so a pair may be connected in both directions
+    Directed,
 }
 
-#[derive(Resource, Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EdgeWeightSource {
+    /// Every edge has a weight of 1
+    Uniform,
+    /// An edge's weight is its length when the graph was computed, relative to the average length
+    Distance,
+    /// Edges are weighted from a file, and those it doesn't list have a weight of 1
+    Imported,
+}
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GraphSpawnConfig {
     pub compute_neighbors_method: ComputeNeighborsMethod,
     pub max_distance: f32,
     pub k_nearest: usize,
+    pub beta: f32,
+    pub edge_mode: EdgeMode,
+    pub edge_weight_source: EdgeWeightSource,
+    #[serde(default = "default_edge_weights_path")]
+    pub edge_weights_path: String,
+}
+
+fn default_edge_weights_path() -> String {
+    EDGE_WEIGHTS_PATH.into()
 }
 
 impl Default for GraphSpawnConfig {
@@ -27,6 +72,10 @@ impl Default for GraphSpawnConfig {
             compute_neighbors_method: ComputeNeighborsMethod::KNearest,
             max_distance: COMPUTE_NEIGHBORS_MAX_DISTANCE,
             k_nearest: COMPUTE_NEIGHBORS_K_NEAREST,
+            beta: COMPUTE_NEIGHBORS_BETA,
+            edge_mode: EdgeMode::Undirected,
+            edge_weight_source: EdgeWeightSource::Uniform,
+            edge_weights_path: EDGE_WEIGHTS_PATH.into(),
         }
     }
 }
@@ -48,6 +97,132 @@ pub struct Partner {
     pub partner: Option<Entity>
 }
 
+/// A connection between two Dots, spawned from their `Neighbors` by `spawn_edges`
+#[derive(Component, Clone, Debug, Serialize, Deserialize)]
+pub struct Edge {
+    pub source: Entity,
+    pub target: Entity,
+    pub weight: f32,
+    /// Overrides `PhysicsConfig::spring_resting_length` for this edge
+    pub rest_length: Option<f32>,
+    /// Overrides `PhysicsConfig::spring_coefficient` for this edge
+    pub stiffness: Option<f32>,
+    /// Drawn instead of `StyleConfig::edge_color` while edge colours aren't mapped to anything
+    pub color: Option<Color>,
+    pub label: Option<String>,
+}
+
+impl Edge {
+    pub fn new(source: Entity, target: Entity) -> Self {
+        Edge {
+            source,
+            target,
+            weight: 1.,
+            rest_length: None,
+            stiffness: None,
+            color: None,
+            label: None,
+        }
+    }
+}
+
+/// An index-based, undirected snapshot of the graph described by every Dot's `Neighbors`
+/// component. `Neighbors` may only list a connection on one side, so both directions are added.
+pub struct Adjacency {
+    pub entities: Vec<Entity>,
+    pub adjacent: Vec<Vec<usize>>,
+}
+
+impl Adjacency {
+    pub fn from_neighbors<'a>(nodes: impl Iterator<Item = (Entity, &'a Neighbors)>) -> Self {
+        let nodes: Vec<(Entity, &Neighbors)> = nodes.collect();
+        let entities: Vec<Entity> = nodes.iter().map(|(eid, _)| *eid).collect();
+        let index_of: HashMap<Entity, usize> = entities.iter().enumerate().map(|(i, eid)| (*eid, i)).collect();
+
+        let mut adjacent = vec![Vec::new(); entities.len()];
+        for (i, (_, neighbors)) in nodes.iter().enumerate() {
+            for neighbor_eid in neighbors.neighbors.iter() {
+                let Some(&j) = index_of.get(neighbor_eid) else { continue };
+                if i == j {
+                    continue;
+                }
+                if !adjacent[i].contains(&j) {
+                    adjacent[i].push(j);
+                }
+                if !adjacent[j].contains(&i) {
+                    adjacent[j].push(i);
+                }
+            }
+        }
+
+        Adjacency { entities, adjacent }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+}
+
+/// Computes the hop count between every pair of nodes with a breadth-first search from each node.
+/// Unreachable pairs are `f32::INFINITY`.
+pub fn all_pairs_shortest_paths(adjacent: &[Vec<usize>]) -> Vec<Vec<f32>> {
+    let n = adjacent.len();
+    let mut distances = vec![vec![f32::INFINITY; n]; n];
+
+    for (source, row) in distances.iter_mut().enumerate() {
+        let mut queue = VecDeque::new();
+        row[source] = 0.;
+        queue.push_back(source);
+
+        while let Some(current) = queue.pop_front() {
+            for &next in adjacent[current].iter() {
+                if row[next].is_infinite() {
+                    row[next] = row[current] + 1.;
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    distances
+}
+
+/// Labels every node with the index of its connected component, largest component first
+pub fn connected_components(adjacency: &Adjacency) -> Vec<usize> {
+    let n = adjacency.len();
+    let mut component = vec![usize::MAX; n];
+    let mut sizes = Vec::new();
+
+    for start in 0..n {
+        if component[start] != usize::MAX {
+            continue;
+        }
+        let id = sizes.len();
+        let mut size = 0;
+        let mut stack = vec![start];
+        component[start] = id;
+        while let Some(current) = stack.pop() {
+            size += 1;
+            for &next in adjacency.adjacent[current].iter() {
+                if component[next] == usize::MAX {
+                    component[next] = id;
+                    stack.push(next);
+                }
+            }
+        }
+        sizes.push(size);
+    }
+
+    // relabel so that the largest component is 0
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&id| std::cmp::Reverse(sizes[id]));
+    let mut rank = vec![0; sizes.len()];
+    for (r, &id) in order.iter().enumerate() {
+        rank[id] = r;
+    }
+    component.iter().map(|&id| rank[id]).collect()
+}
+
 #[derive(PartialEq, Eq)]
 struct MinHeapEntry {
     reverse_cmp_distance_sq: NotNan<f32>,
@@ -74,6 +249,49 @@ pub fn compute_neighbors(
     match graph_spawn_config.compute_neighbors_method {
         ComputeNeighborsMethod::KNearest => compute_neighbors_by_k_nearest(q, graph_spawn_config),
         ComputeNeighborsMethod::Distance => compute_neighbors_by_distance(q, graph_spawn_config),
+        ComputeNeighborsMethod::Delaunay
+        | ComputeNeighborsMethod::Gabriel
+        | ComputeNeighborsMethod::RelativeNeighborhood
+        | ComputeNeighborsMethod::MinimumSpanningTree
+        | ComputeNeighborsMethod::BetaSkeleton => compute_neighbors_geometric(q, graph_spawn_config),
+    }
+}
+
+/// Makes connections between nodes using one of the proximity graphs built on top of the
+/// Delaunay triangulation. Each edge is only added to one of its endpoints.
+pub fn compute_neighbors_geometric(
+    mut q: Query<(&Transform, &mut Neighbors, Entity), With<Dot>>,
+    graph_spawn_config: Res<GraphSpawnConfig>
+) {
+    // remove all existing neighbors
+    for (_, mut neighbors, _) in q.iter_mut() {
+        neighbors.neighbors.clear();
+    }
+
+    let (points, eids): (Vec<Vec2>, Vec<Entity>) = q.iter()
+        .map(|(transform, _, eid)| (transform.translation.xy(), eid))
+        .unzip();
+
+    let delaunay = delaunay_edges(&points);
+    let edges = match graph_spawn_config.compute_neighbors_method {
+        ComputeNeighborsMethod::Gabriel => beta_skeleton_edges(&points, &delaunay, 1.),
+        ComputeNeighborsMethod::RelativeNeighborhood => beta_skeleton_edges(&points, &delaunay, 2.),
+        ComputeNeighborsMethod::MinimumSpanningTree => minimum_spanning_tree_edges(&points, &delaunay),
+        ComputeNeighborsMethod::BetaSkeleton if graph_spawn_config.beta >= 1. => {
+            // these are subgraphs of the Gabriel graph, so Delaunay edges are the only candidates
+            beta_skeleton_edges(&points, &delaunay, graph_spawn_config.beta)
+        }
+        ComputeNeighborsMethod::BetaSkeleton => {
+            let all_pairs: Vec<(usize, usize)> = (0..points.len())
+                .flat_map(|i| (i + 1..points.len()).map(move |j| (i, j)))
+                .collect();
+            beta_skeleton_edges(&points, &all_pairs, graph_spawn_config.beta)
+        }
+        _ => delaunay,
+    };
+
+    for (i, j) in edges {
+        q.get_mut(eids[i]).unwrap().1.neighbors.push(eids[j]);
     }
 }
 
@@ -181,3 +399,127 @@ pub fn compute_disjoint_pairs(
         neighbors_partner.partner = Some(eid);
     }
 }
+
+/// Reads the weights of edges between Dots, which are given by the order they're spawned in
+pub fn load_edge_weights(path: &str) -> Result<Vec<(usize, usize, f32)>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    ron::from_str(&contents).map_err(|e| e.to_string())
+}
+
+pub fn clear_edges(
+    mut commands: Commands,
+    q: Query<Entity, With<Edge>>,
+) {
+    for e in q.iter() {
+        commands.entity(e).despawn();
+    }
+}
+
+/// Replaces every `Edge` entity with new ones built from the Dots' `Neighbors`. Should run after
+/// `compute_neighbors`.
+pub fn spawn_edges(
+    mut commands: Commands,
+    dots_q: Query<(&Neighbors, &Transform, Entity), With<Dot>>,
+    edges_q: Query<Entity, With<Edge>>,
+    graph_spawn_config: Res<GraphSpawnConfig>,
+) {
+    for e in edges_q.iter() {
+        commands.entity(e).despawn();
+    }
+
+    let mut spawned = HashSet::new();
+    let mut edges = Vec::new();
+    for (neighbors, _, eid) in dots_q.iter() {
+        for neighbor_eid in neighbors.neighbors.iter().cloned() {
+            let key = match graph_spawn_config.edge_mode {
+                EdgeMode::Directed => (eid, neighbor_eid),
+                EdgeMode::Undirected => (eid.min(neighbor_eid), eid.max(neighbor_eid)),
+            };
+            if eid != neighbor_eid && spawned.insert(key) {
+                edges.push(Edge::new(eid, neighbor_eid));
+            }
+        }
+    }
+
+    match graph_spawn_config.edge_weight_source {
+        EdgeWeightSource::Uniform => {}
+        EdgeWeightSource::Distance => {
+            for edge in edges.iter_mut() {
+                let [(_, source, _), (_, target, _)] = dots_q.many([edge.source, edge.target]);
+                edge.weight = source.translation.distance(target.translation);
+            }
+            let mean = edges.iter().map(|edge| edge.weight).sum::<f32>() / edges.len().max(1) as f32;
+            if mean > 0. {
+                for edge in edges.iter_mut() {
+                    edge.weight /= mean;
+                }
+            }
+        }
+        EdgeWeightSource::Imported => {
+            let weights = load_edge_weights(&graph_spawn_config.edge_weights_path).unwrap_or_else(|e| {
+                warn!("could not import edge weights \"{}\": {}", graph_spawn_config.edge_weights_path, e);
+                Vec::new()
+            });
+            // Dots are queried in the order they were spawned
+            let entities: Vec<Entity> = dots_q.iter().map(|(_, _, eid)| eid).collect();
+            let mut imported = HashMap::new();
+            for (source, target, weight) in weights {
+                let (Some(&source), Some(&target)) = (entities.get(source), entities.get(target)) else { continue };
+                imported.insert((source, target), weight);
+            }
+            for edge in edges.iter_mut() {
+                let weight = imported.get(&(edge.source, edge.target));
+                // undirected edges may be listed either way round
+                let weight = match graph_spawn_config.edge_mode {
+                    EdgeMode::Directed => weight,
+                    EdgeMode::Undirected => weight.or_else(|| imported.get(&(edge.target, edge.source))),
+                };
+                if let Some(&weight) = weight {
+                    edge.weight = weight;
+                }
+            }
+        }
+    }
+
+    commands.spawn_batch(edges.into_iter().map(|edge| (edge, EdgeStyle::default())));
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    #[test]
+    fn imports_edge_weights_in_spawn_order() {
+        let path = std::env::temp_dir().join("graph-physics-edge-weights-test.ron");
+        // the second is listed the other way round, and the last isn't an edge
+        fs::write(&path, "[(0, 1, 2.5), (2, 1, 0.5), (0, 2, 9.0)]").unwrap();
+
+        let mut world = World::new();
+        world.insert_resource(GraphSpawnConfig {
+            edge_weight_source: EdgeWeightSource::Imported,
+            edge_weights_path: path.to_str().unwrap().to_owned(),
+            ..default()
+        });
+        let dots: Vec<Entity> = (0..4).map(|i| world.spawn((Dot, Transform::from_xyz(i as f32, 0., 0.))).id()).collect();
+        for (i, &eid) in dots.iter().enumerate() {
+            let neighbors = [i.checked_sub(1), Some(i + 1).filter(|&j| j < dots.len())];
+            world.entity_mut(eid).insert(Neighbors { neighbors: neighbors.into_iter().flatten().map(|j| dots[j]).collect() });
+        }
+
+        world.run_system_once(spawn_edges);
+        let _ = fs::remove_file(&path);
+
+        let mut weights: Vec<(usize, usize, f32)> = world.query::<&Edge>()
+            .iter(&world)
+            .map(|edge| {
+                let index = |eid| dots.iter().position(|&dot| dot == eid).unwrap();
+                let (a, b) = (index(edge.source), index(edge.target));
+                (a.min(b), a.max(b), edge.weight)
+            })
+            .collect();
+        weights.sort_by_key(|&(a, b, _)| (a, b));
+        assert_eq!(weights, vec![(0, 1, 2.5), (1, 2, 0.5), (2, 3, 1.)]);
+    }
+}
@@ -0,0 +1,275 @@
+use bevy::{ecs::system::RunSystemOnce, log::LogPlugin, prelude::*, time::TimeUpdateStrategy};
+
+use crate::{add_simulation, export::{write_png, write_svg, ExportScene}, preset::Preset, quality::{measure_layout_quality, LayoutQualityConfig, LayoutQualityLog}, record::{finish_recording, fixed_timestep, RecordConfig, RecordFormat, Recorder, RECORD_DIRECTORY}, replay::{begin_replay, Replay, ReplayMode}, snapshot::{SnapshotRequests, WorldSnapshot}, sweep::{run_sweep, SWEEP_REPORT_PATH}};
+
+/// How many frames to simulate when `--steps` isn't given
+const HEADLESS_STEPS: u32 = 600;
+
+const USAGE: &str = "usage: graph-physics [--headless [--steps N] [--export-svg PATH] [--export-png PATH] [--record DIRECTORY [--apng]] [--record-replay PATH | --replay PATH] [--load-snapshot PATH] [--save-snapshot PATH] [--preset PATH] [--quality PATH] | --sweep PATH [--sweep-report PATH]]";
+
+/// Command line options for running without a window
+#[derive(Debug)]
+pub struct HeadlessArgs {
+    pub steps: u32,
+    pub export_svg: Option<String>,
+    pub export_png: Option<String>,
+    /// Write a frame for every step to this directory
+    pub record: Option<String>,
+    pub apng: bool,
+    /// Record the seed, config and inputs of the run to this file
+    pub record_replay: Option<String>,
+    /// Play back a recorded replay and report whether it still matches
+    pub replay: Option<String>,
+    /// Start from a saved snapshot instead of freshly spawned dots
+    pub load_snapshot: Option<String>,
+    pub save_snapshot: Option<String>,
+    /// Start with the physics, boundary and obstacles saved in this preset
+    pub preset: Option<String>,
+    /// Measure layout quality throughout the run and write every sample to this CSV file
+    pub quality: Option<String>,
+    /// Run every configuration in this sweep file instead, and report on each
+    pub sweep: Option<String>,
+    pub sweep_report: String,
+}
+
+impl HeadlessArgs {
+    /// Parses the process arguments. Returns `None` if `--headless` isn't given
+    pub fn from_env() -> Result<Option<Self>, String> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut headless = false;
+        let mut parsed = HeadlessArgs {
+            steps: HEADLESS_STEPS,
+            export_svg: None,
+            export_png: None,
+            record: None,
+            apng: false,
+            record_replay: None,
+            replay: None,
+            load_snapshot: None,
+            save_snapshot: None,
+            preset: None,
+            quality: None,
+            sweep: None,
+            sweep_report: SWEEP_REPORT_PATH.to_owned(),
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value\n{}", arg, USAGE));
+            match arg.as_str() {
+                "--headless" => headless = true,
+                "--steps" => parsed.steps = value()?.parse().map_err(|e| format!("invalid --steps: {}\n{}", e, USAGE))?,
+                "--export-svg" => parsed.export_svg = Some(value()?),
+                "--export-png" => parsed.export_png = Some(value()?),
+                "--record" => parsed.record = Some(value()?),
+                "--apng" => parsed.apng = true,
+                "--record-replay" => parsed.record_replay = Some(value()?),
+                "--replay" => parsed.replay = Some(value()?),
+                "--load-snapshot" => parsed.load_snapshot = Some(value()?),
+                "--save-snapshot" => parsed.save_snapshot = Some(value()?),
+                "--preset" => parsed.preset = Some(value()?),
+                "--quality" => parsed.quality = Some(value()?),
+                "--sweep" => parsed.sweep = Some(value()?),
+                "--sweep-report" => parsed.sweep_report = value()?,
+                _ => return Err(format!("unknown argument {}\n{}", arg, USAGE)),
+            }
+        }
+
+        Ok(headless.then_some(parsed))
+    }
+}
+
+/// An app that simulates without a window, advancing exactly one fixed timestep every frame
+pub fn headless_app(log: bool) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(TimeUpdateStrategy::ManualDuration(fixed_timestep()));
+    if log {
+        app.add_plugins(LogPlugin::default());
+    }
+    add_simulation(&mut app);
+    app.world.resource_mut::<RecordConfig>().deterministic = true;
+    app
+}
+
+/// Runs the simulation for a fixed number of frames without a window, then exports the last frame.
+/// Every frame advances time by exactly one fixed timestep, so runs are reproducible.
+pub fn run_headless(args: HeadlessArgs) {
+    if let Some(path) = args.sweep {
+        run_sweep(&path, &args.sweep_report);
+        return;
+    }
+
+    let mut app = headless_app(true);
+    app.insert_resource(RecordConfig {
+        format: if args.apng { RecordFormat::Apng } else { RecordFormat::PngSequence },
+        directory: args.record.clone().unwrap_or(RECORD_DIRECTORY.to_owned()),
+        deterministic: true,
+        ..Default::default()
+    });
+    if args.record.is_some() {
+        app.insert_resource(Recorder { recording: true, frames: 0 });
+    }
+    let replay_request = match (args.record_replay, args.replay) {
+        (Some(path), _) => Some((ReplayMode::Recording, path)),
+        (None, Some(path)) => Some((ReplayMode::Playing, path)),
+        (None, None) => None,
+    };
+    if let Some((mode, path)) = replay_request {
+        let mut replay = app.world.resource_mut::<Replay>();
+        replay.requested = Some(mode);
+        replay.path = path;
+    }
+
+    if let Some(path) = args.preset {
+        match Preset::load(&path) {
+            Ok(preset) => preset.apply(&mut app.world),
+            Err(e) => error!("could not load preset from {}: {}", path, e),
+        }
+    }
+
+    if args.quality.is_some() {
+        let mut config = app.world.resource_mut::<LayoutQualityConfig>();
+        config.continuous = true;
+        config.history_len = args.steps as usize + 1;
+    }
+
+    // handled at the end of the first frame, replacing the dots that were just spawned
+    app.world.resource_mut::<SnapshotRequests>().load = args.load_snapshot;
+
+    app.finish();
+    app.cleanup();
+    for _ in 0..args.steps {
+        app.update();
+    }
+
+    app.world.resource_mut::<Recorder>().recording = false;
+    app.world.run_system_once(finish_recording);
+
+    match app.world.resource::<Replay>().mode {
+        ReplayMode::Recording => {
+            app.world.resource_mut::<Replay>().requested = Some(ReplayMode::Idle);
+            app.world.run_system_once(begin_replay);
+        }
+        ReplayMode::Playing | ReplayMode::Idle if app.world.resource::<Replay>().timeline.is_some() => {
+            let replay = app.world.resource::<Replay>();
+            match replay.diverged_at {
+                Some(frame) => error!("replay diverged at frame {}", frame),
+                None => info!("replay matched the recording for {} frames", replay.frame),
+            }
+        }
+        _ => {}
+    }
+
+    if let Some(path) = args.save_snapshot {
+        match WorldSnapshot::capture(&mut app.world).save(&path) {
+            Ok(()) => info!("saved snapshot to {}", path),
+            Err(e) => error!("could not save snapshot to {}: {}", path, e),
+        }
+    }
+
+    if let Some(path) = args.quality {
+        // finish with the final layout, whether or not a sample was due
+        app.world.resource_mut::<LayoutQualityConfig>().requested = true;
+        app.world.run_system_once(measure_layout_quality);
+        match app.world.resource::<LayoutQualityLog>().write_csv(&path) {
+            Ok(()) => info!("wrote layout quality to {}", path),
+            Err(e) => error!("could not write layout quality to {}: {}", path, e),
+        }
+    }
+
+    let scene = ExportScene::from_world(&mut app.world);
+    if let Some(path) = args.export_svg {
+        match write_svg(&scene, &path) {
+            Ok(()) => info!("exported SVG to {}", path),
+            Err(e) => error!("could not export SVG to {}: {}", path, e),
+        }
+    }
+    if let Some(path) = args.export_png {
+        match write_png(&scene, &path) {
+            Ok(()) => info!("exported PNG to {}", path),
+            Err(e) => error!("could not export PNG to {}: {}", path, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::phases::Phases;
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<HeadlessArgs>, String> {
+        HeadlessArgs::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_headless_options() {
+        let args = parse(&["--headless", "--steps", "10", "--apng", "--record", "frames"]).unwrap().unwrap();
+        assert_eq!(args.steps, 10);
+        assert!(args.apng);
+        assert_eq!(args.record.as_deref(), Some("frames"));
+        assert_eq!(args.export_svg, None);
+    }
+
+    #[test]
+    fn runs_windowed_without_headless() {
+        assert!(parse(&[]).unwrap().is_none());
+        assert!(parse(&["--steps", "10"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_missing_values() {
+        let err = parse(&["--headless", "--export-svg"]).unwrap_err();
+        assert!(err.starts_with("--export-svg needs a value"));
+        assert!(err.contains(USAGE));
+        assert!(parse(&["--headless", "--steps", "many"]).is_err());
+    }
+
+    /// Runs a headless app for `steps` frames while it records or plays the replay at `path`,
+    /// switching to the graph phase partway through
+    fn run_replay(mode: ReplayMode, path: &str, steps: u32) -> App {
+        let mut app = headless_app(false);
+        let mut replay = app.world.resource_mut::<Replay>();
+        replay.requested = Some(mode);
+        replay.path = path.to_owned();
+        app.finish();
+        app.cleanup();
+        for step in 0..steps {
+            if mode == ReplayMode::Recording && step == steps / 2 {
+                app.world.resource_mut::<NextState<Phases>>().set(Phases::Graph);
+            }
+            app.update();
+        }
+        app
+    }
+
+    #[test]
+    fn replays_match_their_recording() {
+        let path = std::env::temp_dir().join("graph-physics-replay-test.ron");
+        let path = path.to_str().unwrap();
+
+        let mut recorded = run_replay(ReplayMode::Recording, path, 20);
+        recorded.world.resource_mut::<Replay>().requested = Some(ReplayMode::Idle);
+        recorded.world.run_system_once(begin_replay);
+
+        let played = run_replay(ReplayMode::Playing, path, 20);
+        let _ = std::fs::remove_file(path);
+        let replay = played.world.resource::<Replay>();
+        assert_eq!(replay.frame, 20);
+        // there were Dots to compare
+        assert!(replay.timeline.as_ref().is_some_and(|timeline| timeline.frames.iter().all(|frame| !frame.positions.is_empty())));
+        assert_eq!(replay.diverged_at, None);
+        assert_eq!(*played.world.resource::<State<Phases>>().get(), Phases::Graph);
+    }
+
+    #[test]
+    fn rejects_unknown_arguments() {
+        let err = parse(&["--headless", "--fast"]).unwrap_err();
+        assert!(err.starts_with("unknown argument --fast"));
+        assert!(err.contains(USAGE));
+    }
+}
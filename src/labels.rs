@@ -0,0 +1,197 @@
+use bevy::{prelude::*, sprite::Anchor, text::TextLayoutInfo, utils::{HashMap, HashSet}};
+
+use crate::{graph::{Dot, Edge}, phases::Phases, MainCamera};
+
+const LABEL_FONT_SIZE: f32 = 12.;
+/// Labels are hidden once the camera is zoomed out past this projection scale
+const LABEL_MAX_ZOOM_OUT: f32 = 1.5;
+/// The gap between a dot and its label
+const LABEL_OFFSET: f32 = 6.;
+/// Keeps labels in front of dots and edges
+const LABEL_Z: f32 = 1.;
+
+#[derive(Resource, Debug)]
+pub struct LabelConfig {
+    pub show_dot_labels: bool,
+    pub show_edge_labels: bool,
+    pub font_size: f32,
+    pub max_zoom_out: f32,
+    /// Hide labels which would overlap a label that has already been placed
+    pub avoid_overlap: bool,
+}
+
+impl Default for LabelConfig {
+    fn default() -> Self {
+        LabelConfig {
+            show_dot_labels: false,
+            show_edge_labels: false,
+            font_size: LABEL_FONT_SIZE,
+            max_zoom_out: LABEL_MAX_ZOOM_OUT,
+            avoid_overlap: true,
+        }
+    }
+}
+
+/// A text entity labelling a Dot, or an Edge
+#[derive(Component)]
+pub struct GraphLabel {
+    pub target: Entity,
+}
+
+/// The text for a Dot's label: its `Name` if it has one, otherwise its entity ID
+pub fn dot_label_text(eid: Entity, name: Option<&Name>) -> String {
+    match name {
+        Some(name) => name.as_str().to_owned(),
+        None => eid.index().to_string(),
+    }
+}
+
+/// The text for an Edge's label: its label if it has one, otherwise its weight
+fn edge_label_text(edge: &Edge) -> String {
+    match &edge.label {
+        Some(label) => label.clone(),
+        None => format!("{:.2}", edge.weight),
+    }
+}
+
+/// Spawns a label for every Dot and Edge that doesn't have one while their labels are turned on,
+/// and despawns labels whose target no longer exists or whose kind has been turned off
+pub fn spawn_labels(
+    mut commands: Commands,
+    dots_q: Query<(Entity, Option<&Name>), With<Dot>>,
+    edges_q: Query<(Entity, &Edge)>,
+    labels_q: Query<(Entity, &GraphLabel)>,
+    label_config: Res<LabelConfig>,
+) {
+    let mut labelled = HashSet::new();
+    for (e, label) in labels_q.iter() {
+        let wanted = (label_config.show_dot_labels && dots_q.contains(label.target))
+            || (label_config.show_edge_labels && edges_q.contains(label.target));
+        if wanted {
+            labelled.insert(label.target);
+        } else {
+            commands.entity(e).despawn();
+        }
+    }
+
+    let style = TextStyle {
+        font_size: label_config.font_size,
+        color: Color::WHITE,
+        ..Default::default()
+    };
+    let mut spawn_label = |target: Entity, text: String| {
+        commands.spawn((
+            GraphLabel { target },
+            Text2dBundle {
+                text: Text::from_section(text, style.clone()),
+                visibility: Visibility::Hidden,
+                ..Default::default()
+            },
+        ));
+    };
+
+    if label_config.show_dot_labels {
+        for (eid, name) in dots_q.iter().filter(|(eid, _)| !labelled.contains(eid)) {
+            spawn_label(eid, dot_label_text(eid, name));
+        }
+    }
+    if label_config.show_edge_labels {
+        for (eid, edge) in edges_q.iter().filter(|(eid, _)| !labelled.contains(eid)) {
+            spawn_label(eid, edge_label_text(edge));
+        }
+    }
+}
+
+fn overlaps(a: Rect, placed: &[Rect]) -> bool {
+    placed.iter().any(|b| !a.intersect(*b).is_empty())
+}
+
+/// Moves every label next to its target and decides which ones are shown. Labels are hidden when
+/// zoomed out, and otherwise placed greedily (dots with more edges first) at the first candidate
+/// position that doesn't overlap a label that is already placed.
+pub fn update_labels(
+    mut labels_q: Query<(&GraphLabel, &mut Transform, &mut Visibility, &mut Anchor, &mut Text, &TextLayoutInfo)>,
+    dots_q: Query<&Transform, (With<Dot>, Without<GraphLabel>)>,
+    edges_q: Query<(Entity, &Edge)>,
+    camera_q: Query<&OrthographicProjection, With<MainCamera>>,
+    phase: Res<State<Phases>>,
+    label_config: Res<LabelConfig>,
+) {
+    let zoomed_in = camera_q.get_single().map_or(true, |projection| projection.scale <= label_config.max_zoom_out);
+    let show_dots = label_config.show_dot_labels && zoomed_in && matches!(phase.get(), Phases::JustDots | Phases::Graph);
+    let show_edges = label_config.show_edge_labels && zoomed_in && *phase.get() == Phases::Graph;
+
+    let mut degrees = HashMap::new();
+    for (_, edge) in edges_q.iter() {
+        *degrees.entry(edge.source).or_insert(0) += 1;
+        *degrees.entry(edge.target).or_insert(0) += 1;
+    }
+
+    // (priority, label, anchor position, size)
+    let mut candidates = Vec::new();
+    for (label, mut tf, mut visibility, _, mut text, layout) in labels_q.iter_mut() {
+        visibility.set_if_neq(Visibility::Hidden);
+        if text.sections[0].style.font_size != label_config.font_size {
+            text.sections[0].style.font_size = label_config.font_size;
+        }
+        // until the text has been laid out, guess its size from its length
+        let size = if layout.logical_size == Vec2::ZERO {
+            Vec2::new(text.sections[0].value.len() as f32 * label_config.font_size * 0.6, label_config.font_size)
+        } else {
+            layout.logical_size
+        };
+
+        let (priority, pos) = if let Ok(dot_tf) = dots_q.get(label.target) {
+            if !show_dots {
+                continue;
+            }
+            (degrees.get(&label.target).cloned().unwrap_or(0) + 1_000_000, dot_tf.translation.xy())
+        } else if let Ok((_, edge)) = edges_q.get(label.target) {
+            if !show_edges {
+                continue;
+            }
+            let Ok([a, b]) = dots_q.get_many([edge.source, edge.target]) else { continue };
+            (0, (a.translation.xy() + b.translation.xy()) / 2.)
+        } else {
+            continue;
+        };
+
+        // the camera flips y, so flip the text back
+        tf.scale = Vec3::new(1., -1., 1.);
+        tf.translation = pos.extend(LABEL_Z);
+        candidates.push((priority, label.target, pos, size));
+    }
+
+    candidates.sort_by_key(|(priority, ..)| std::cmp::Reverse(*priority));
+
+    // y grows downwards on screen, so "above" is negative y
+    let placements = [
+        (Vec2::new(LABEL_OFFSET, 0.), Anchor::CenterLeft, Vec2::new(0., -0.5)),
+        (Vec2::new(-LABEL_OFFSET, 0.), Anchor::CenterRight, Vec2::new(-1., -0.5)),
+        (Vec2::new(0., -LABEL_OFFSET), Anchor::BottomCenter, Vec2::new(-0.5, -1.)),
+        (Vec2::new(0., LABEL_OFFSET), Anchor::TopCenter, Vec2::new(-0.5, 0.)),
+    ];
+
+    let mut placed: Vec<Rect> = Vec::new();
+    let mut chosen = HashMap::new();
+    for (_, target, pos, size) in candidates {
+        for (offset, anchor, corner) in placements.iter() {
+            let min = pos + *offset + *corner * size;
+            let rect = Rect::from_corners(min, min + size);
+            if !label_config.avoid_overlap || !overlaps(rect, &placed) {
+                placed.push(rect);
+                chosen.insert(target, (*offset, *anchor));
+                break;
+            }
+        }
+    }
+
+    for (label, mut tf, mut visibility, mut anchor, _, _) in labels_q.iter_mut() {
+        if let Some((offset, new_anchor)) = chosen.remove(&label.target) {
+            tf.translation += offset.extend(0.);
+            // the text is flipped back upright, so its anchors match what is seen on screen
+            *anchor = new_anchor;
+            *visibility = Visibility::Inherited;
+        }
+    }
+}
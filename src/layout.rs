@@ -0,0 +1,512 @@
+use bevy::prelude::*;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::{graph::{all_pairs_shortest_paths, Adjacency, Dot, Neighbors}, phases::SpawnMethod, physics::{Acceleration, Velocity}, Randomness, WIN_SIZE};
+
+/// The ideal length of a single edge. Graph distances are multiplied by this to get target
+/// Euclidean distances
+pub const STRESS_EDGE_LENGTH: f32 = 50.;
+/// The number of SMACOF iterations performed when running as a one-shot
+pub const STRESS_ONE_SHOT_ITERATIONS: usize = 300;
+/// The number of SMACOF iterations performed every frame when running incrementally
+pub const STRESS_ITERATIONS_PER_FRAME: usize = 1;
+/// The number of power iterations used to approximate each Laplacian eigenvector
+const SPECTRAL_POWER_ITERATIONS: usize = 500;
+/// Coarsening stops once a level has at most this many nodes
+const MULTILEVEL_COARSEST_SIZE: usize = 16;
+/// The number of SMACOF iterations used to refine each level after prolongation
+const MULTILEVEL_REFINE_ITERATIONS: usize = 30;
+/// How far from the window edges initial placements are kept
+const INITIAL_PLACEMENT_MARGIN: f32 = 100.;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StressLayoutMode {
+    Off,
+    /// Solve the whole layout when the graph is computed
+    OneShot,
+    /// Perform a few iterations every frame
+    Incremental,
+}
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StressLayoutConfig {
+    pub mode: StressLayoutMode,
+    pub edge_length: f32,
+    pub one_shot_iterations: usize,
+    pub iterations_per_frame: usize,
+}
+
+impl Default for StressLayoutConfig {
+    fn default() -> Self {
+        StressLayoutConfig {
+            mode: StressLayoutMode::Off,
+            edge_length: STRESS_EDGE_LENGTH,
+            one_shot_iterations: STRESS_ONE_SHOT_ITERATIONS,
+            iterations_per_frame: STRESS_ITERATIONS_PER_FRAME,
+        }
+    }
+}
+
+/// The graph distances used by the incremental solver. Only recomputed when `Neighbors` changes
+#[derive(Resource, Default)]
+pub struct StressLayoutCache {
+    adjacency: Option<Adjacency>,
+    graph_distances: Vec<Vec<f32>>,
+}
+
+/// Replaces unreachable distances with one more than the largest finite distance so that
+/// disconnected components are kept apart instead of being ignored
+fn fill_unreachable(graph_distances: &mut [Vec<f32>]) {
+    let max_finite = graph_distances.iter()
+        .flatten()
+        .cloned()
+        .filter(|d| d.is_finite())
+        .fold(0., f32::max);
+
+    for d in graph_distances.iter_mut().flatten() {
+        if d.is_infinite() {
+            *d = max_finite + 1.;
+        }
+    }
+}
+
+/// Performs one iteration of localized stress majorization (Gansner, Koren & North) with weights
+/// `1 / d_ij^2`. Returns the stress of the layout before the update.
+pub fn smacof_iteration(positions: &mut [Vec2], graph_distances: &[Vec<f32>], edge_length: f32) -> f32 {
+    let mut stress = 0.;
+
+    for i in 0..positions.len() {
+        let mut numerator = Vec2::ZERO;
+        let mut denominator = 0.;
+
+        for (j, graph_distance) in graph_distances[i].iter().enumerate() {
+            if i == j {
+                continue;
+            }
+            let target = graph_distance * edge_length;
+            let weight = 1. / (target * target);
+
+            let delta = positions[i] - positions[j];
+            let distance = delta.length();
+            stress += weight * (distance - target).powi(2);
+
+            // nudge coincident dots apart so that they have a direction to move in
+            let direction = if distance == 0. {
+                Vec2::new(((i + j) % 7) as f32 - 3., ((i * j) % 5) as f32 - 2.).normalize_or_zero()
+            } else {
+                delta / distance
+            };
+
+            numerator += weight * (positions[j] + target * direction);
+            denominator += weight;
+        }
+
+        if denominator > 0. {
+            positions[i] = numerator / denominator;
+        }
+    }
+
+    // every pair was counted twice
+    stress / 2.
+}
+
+/// Moves the layout so that its centroid is at the middle of the window
+fn center_positions(positions: &mut [Vec2]) {
+    if positions.is_empty() {
+        return;
+    }
+    let centroid = positions.iter().sum::<Vec2>() / positions.len() as f32;
+    let offset = Vec2::new(WIN_SIZE.0 / 2., WIN_SIZE.1 / 2.) - centroid;
+    for pos in positions.iter_mut() {
+        *pos += offset;
+    }
+}
+
+fn write_positions(
+    q: &mut Query<(Entity, &Neighbors, &mut Transform, &mut Velocity, &mut Acceleration), With<Dot>>,
+    adjacency: &Adjacency,
+    positions: &[Vec2],
+) {
+    for (eid, pos) in adjacency.entities.iter().zip(positions.iter()) {
+        if let Ok((_, _, mut tf, mut vel, mut acc)) = q.get_mut(*eid) {
+            tf.translation.x = pos.x;
+            tf.translation.y = pos.y;
+            // the solver owns the positions, so don't let leftover momentum fight it
+            vel.0 = Vec3::ZERO;
+            acc.0 = Vec3::ZERO;
+        }
+    }
+}
+
+/// Solves the stress majorization layout to completion. Should run after `compute_neighbors`
+pub fn stress_layout_one_shot(
+    mut q: Query<(Entity, &Neighbors, &mut Transform, &mut Velocity, &mut Acceleration), With<Dot>>,
+    stress_config: Res<StressLayoutConfig>,
+) {
+    if stress_config.mode != StressLayoutMode::OneShot {
+        return;
+    }
+
+    let adjacency = Adjacency::from_neighbors(q.iter().map(|(eid, neighbors, ..)| (eid, neighbors)));
+    let mut graph_distances = all_pairs_shortest_paths(&adjacency.adjacent);
+    fill_unreachable(&mut graph_distances);
+
+    let mut positions: Vec<Vec2> = adjacency.entities.iter()
+        .map(|eid| q.get(*eid).unwrap().2.translation.xy())
+        .collect();
+
+    let mut stress = 0.;
+    for _ in 0..stress_config.one_shot_iterations {
+        stress = smacof_iteration(&mut positions, &graph_distances, stress_config.edge_length);
+    }
+    debug!("stress after {} iterations: {}", stress_config.one_shot_iterations, stress);
+
+    center_positions(&mut positions);
+    write_positions(&mut q, &adjacency, &positions);
+}
+
+/// Performs a few stress majorization iterations per frame so the layout can be watched as it
+/// converges
+pub fn stress_layout_incremental(
+    mut q: Query<(Entity, &Neighbors, &mut Transform, &mut Velocity, &mut Acceleration), With<Dot>>,
+    changed: Query<(), Changed<Neighbors>>,
+    stress_config: Res<StressLayoutConfig>,
+    mut cache: ResMut<StressLayoutCache>,
+) {
+    if stress_config.mode != StressLayoutMode::Incremental {
+        return;
+    }
+
+    let stale = match &cache.adjacency {
+        Some(adjacency) => !changed.is_empty() || adjacency.len() != q.iter().len(),
+        None => true,
+    };
+    if stale {
+        let adjacency = Adjacency::from_neighbors(q.iter().map(|(eid, neighbors, ..)| (eid, neighbors)));
+        let mut graph_distances = all_pairs_shortest_paths(&adjacency.adjacent);
+        fill_unreachable(&mut graph_distances);
+        cache.graph_distances = graph_distances;
+        cache.adjacency = Some(adjacency);
+    }
+
+    let cache = cache.as_ref();
+    let adjacency = cache.adjacency.as_ref().unwrap();
+
+    let mut positions: Vec<Vec2> = Vec::with_capacity(adjacency.len());
+    for eid in adjacency.entities.iter() {
+        match q.get(*eid) {
+            Ok((_, _, tf, ..)) => positions.push(tf.translation.xy()),
+            // a dot was despawned since the cache was built; wait for it to be rebuilt
+            Err(_) => return,
+        }
+    }
+
+    for _ in 0..stress_config.iterations_per_frame {
+        smacof_iteration(&mut positions, &cache.graph_distances, stress_config.edge_length);
+    }
+
+    center_positions(&mut positions);
+    write_positions(&mut q, adjacency, &positions);
+}
+
+/// Removes the components of `v` along the constant vector and every vector in `basis`.
+/// Assumes the vectors in `basis` are normalized.
+fn orthogonalize(v: &mut [f32], basis: &[Vec<f32>]) {
+    let mean = v.iter().sum::<f32>() / v.len() as f32;
+    for x in v.iter_mut() {
+        *x -= mean;
+    }
+    for b in basis {
+        let dot: f32 = v.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+        for (x, y) in v.iter_mut().zip(b.iter()) {
+            *x -= dot * y;
+        }
+    }
+}
+
+fn normalize(v: &mut [f32]) {
+    let length = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if length > 0. {
+        for x in v.iter_mut() {
+            *x /= length;
+        }
+    }
+}
+
+/// Lays out a graph using the eigenvectors of its Laplacian with the second and third smallest
+/// eigenvalues. They are found by power iteration on `cI - L`, whose largest eigenvectors are the
+/// smallest of `L`, deflating against the constant eigenvector.
+pub fn spectral_layout(adjacent: &[Vec<usize>], rng: &mut StdRng) -> Vec<Vec2> {
+    let n = adjacent.len();
+    if n < 3 {
+        return (0..n).map(|i| Vec2::new(i as f32, 0.)).collect();
+    }
+
+    // Gershgorin bound on the largest eigenvalue of L
+    let max_degree = adjacent.iter().map(|a| a.len()).max().unwrap_or(0);
+    let shift = 2. * max_degree as f32 + 1.;
+
+    let mut eigenvectors: Vec<Vec<f32>> = Vec::with_capacity(2);
+    for _ in 0..2 {
+        let mut v: Vec<f32> = (0..n).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        orthogonalize(&mut v, &eigenvectors);
+        normalize(&mut v);
+
+        for _ in 0..SPECTRAL_POWER_ITERATIONS {
+            // next = (cI - L) v = (c - deg(i)) v_i + sum of neighbor values
+            let mut next: Vec<f32> = (0..n)
+                .map(|i| (shift - adjacent[i].len() as f32) * v[i] + adjacent[i].iter().map(|&j| v[j]).sum::<f32>())
+                .collect();
+            orthogonalize(&mut next, &eigenvectors);
+            normalize(&mut next);
+            v = next;
+        }
+
+        eigenvectors.push(v);
+    }
+
+    (0..n).map(|i| Vec2::new(eigenvectors[0][i], eigenvectors[1][i])).collect()
+}
+
+/// Collapses a random maximal matching of the graph. Returns which coarse node each node was
+/// merged into, along with the coarse graph.
+fn coarsen(adjacent: &[Vec<usize>], rng: &mut StdRng) -> (Vec<usize>, Vec<Vec<usize>>) {
+    let n = adjacent.len();
+    let mut parent = vec![usize::MAX; n];
+    let mut coarse_len = 0;
+
+    let mut order: Vec<usize> = (0..n).collect();
+    order.shuffle(rng);
+
+    for i in order {
+        if parent[i] != usize::MAX {
+            continue;
+        }
+        parent[i] = coarse_len;
+        // prefer merging with low degree neighbors so hubs don't swallow everything
+        let partner = adjacent[i].iter()
+            .cloned()
+            .filter(|&j| parent[j] == usize::MAX)
+            .min_by_key(|&j| adjacent[j].len());
+        if let Some(j) = partner {
+            parent[j] = coarse_len;
+        }
+        coarse_len += 1;
+    }
+
+    let mut coarse = vec![Vec::new(); coarse_len];
+    for (i, neighbors) in adjacent.iter().enumerate() {
+        for &j in neighbors {
+            let (a, b) = (parent[i], parent[j]);
+            if a != b && !coarse[a].contains(&b) {
+                coarse[a].push(b);
+            }
+        }
+    }
+
+    (parent, coarse)
+}
+
+/// Lays out a graph by repeatedly coarsening it, placing the coarsest level spectrally, then
+/// prolonging each level back to the finer one and refining with stress majorization
+pub fn multilevel_layout(adjacent: &[Vec<usize>], rng: &mut StdRng) -> Vec<Vec2> {
+    let mut levels: Vec<Vec<Vec<usize>>> = vec![adjacent.to_vec()];
+    let mut parents: Vec<Vec<usize>> = Vec::new();
+
+    loop {
+        let finest = levels.last().unwrap();
+        if finest.len() <= MULTILEVEL_COARSEST_SIZE {
+            break;
+        }
+        let (parent, coarse) = coarsen(finest, rng);
+        // stop if the matching barely shrinks the graph (e.g. mostly isolated nodes)
+        if coarse.len() * 10 > finest.len() * 9 {
+            break;
+        }
+        parents.push(parent);
+        levels.push(coarse);
+    }
+
+    let mut positions = spectral_layout(levels.last().unwrap(), rng);
+    for level in (0..parents.len()).rev() {
+        let fine = &levels[level];
+        positions = parents[level].iter()
+            .map(|&p| positions[p] + Vec2::new(rng.gen_range(-0.1..0.1), rng.gen_range(-0.1..0.1)))
+            .collect();
+
+        let mut graph_distances = all_pairs_shortest_paths(fine);
+        fill_unreachable(&mut graph_distances);
+        for _ in 0..MULTILEVEL_REFINE_ITERATIONS {
+            smacof_iteration(&mut positions, &graph_distances, 1.);
+        }
+    }
+
+    positions
+}
+
+/// Uniformly scales and translates a layout so that it fills the window, keeping its aspect ratio
+fn fit_to_window(positions: &mut [Vec2]) {
+    if positions.is_empty() {
+        return;
+    }
+    let min = positions.iter().cloned().fold(Vec2::splat(f32::INFINITY), Vec2::min);
+    let max = positions.iter().cloned().fold(Vec2::splat(f32::NEG_INFINITY), Vec2::max);
+    let size = (max - min).max(Vec2::splat(f32::EPSILON));
+
+    let available = Vec2::new(WIN_SIZE.0, WIN_SIZE.1) - 2. * INITIAL_PLACEMENT_MARGIN;
+    let scale = f32::min(available.x / size.x, available.y / size.y);
+    let offset = Vec2::new(WIN_SIZE.0, WIN_SIZE.1) / 2. - (min + max) / 2. * scale;
+
+    for pos in positions.iter_mut() {
+        *pos = *pos * scale + offset;
+    }
+}
+
+/// Moves dots to a graph-aware initial placement for the spawn methods that need one. Dots are
+/// spawned randomly, so this has to run after `compute_neighbors` has built the graph.
+pub fn initial_placement(
+    mut q: Query<(Entity, &Neighbors, &mut Transform, &mut Velocity, &mut Acceleration), With<Dot>>,
+    spawn_method: Res<SpawnMethod>,
+    mut randomness: ResMut<Randomness>,
+) {
+    let adjacency = Adjacency::from_neighbors(q.iter().map(|(eid, neighbors, ..)| (eid, neighbors)));
+
+    let mut positions = match *spawn_method {
+        SpawnMethod::Spectral => spectral_layout(&adjacency.adjacent, &mut randomness.0),
+        SpawnMethod::Multilevel => multilevel_layout(&adjacency.adjacent, &mut randomness.0),
+        _ => return,
+    };
+    fit_to_window(&mut positions);
+
+    // components collapse onto a single point spectrally, so spread coincident dots slightly
+    for pos in positions.iter_mut() {
+        *pos += Vec2::new(randomness.0.gen_range(-1.0..1.0), randomness.0.gen_range(-1.0..1.0));
+    }
+
+    write_positions(&mut q, &adjacency, &positions);
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    /// Undirected adjacency lists from a list of edges
+    fn graph(n: usize, edges: &[(usize, usize)]) -> Vec<Vec<usize>> {
+        let mut adjacent = vec![Vec::new(); n];
+        for &(a, b) in edges {
+            adjacent[a].push(b);
+            adjacent[b].push(a);
+        }
+        adjacent
+    }
+
+    fn path(n: usize) -> Vec<Vec<usize>> {
+        graph(n, &(1..n).map(|i| (i - 1, i)).collect::<Vec<_>>())
+    }
+
+    fn cycle(n: usize) -> Vec<Vec<usize>> {
+        graph(n, &(0..n).map(|i| (i, (i + 1) % n)).collect::<Vec<_>>())
+    }
+
+    /// A `columns` by `rows` lattice with each node joined to the ones beside and below it
+    fn grid(columns: usize, rows: usize) -> Vec<Vec<usize>> {
+        let mut edges = Vec::new();
+        for y in 0..rows {
+            for x in 0..columns {
+                let i = y * columns + x;
+                if x + 1 < columns {
+                    edges.push((i, i + 1));
+                }
+                if y + 1 < rows {
+                    edges.push((i, i + columns));
+                }
+            }
+        }
+        graph(columns * rows, &edges)
+    }
+
+    fn connected(adjacent: &[Vec<usize>]) -> bool {
+        all_pairs_shortest_paths(adjacent).iter().flatten().all(|d| d.is_finite())
+    }
+
+    #[test]
+    fn stress_decreases_to_the_graph_distances() {
+        let distances = all_pairs_shortest_paths(&path(5));
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut positions: Vec<Vec2> = (0..5).map(|_| Vec2::new(rng.gen_range(0.0..100.0), rng.gen_range(0.0..100.0))).collect();
+
+        let mut last_stress = f32::INFINITY;
+        for _ in 0..1000 {
+            let stress = smacof_iteration(&mut positions, &distances, STRESS_EDGE_LENGTH);
+            assert!(stress <= last_stress * (1. + 1e-4), "stress went up from {} to {}", last_stress, stress);
+            last_stress = stress;
+        }
+
+        // a path can be laid out in a straight line with every distance exact
+        for i in 0..5 {
+            for j in 0..5 {
+                let target = distances[i][j] * STRESS_EDGE_LENGTH;
+                assert!((positions[i].distance(positions[j]) - target).abs() < 0.01 * STRESS_EDGE_LENGTH, "{} to {} isn't {}", i, j, target);
+            }
+        }
+    }
+
+    #[test]
+    fn unreachable_pairs_are_one_past_the_furthest() {
+        // a path of 3 and a separate edge
+        let mut distances = all_pairs_shortest_paths(&graph(5, &[(0, 1), (1, 2), (3, 4)]));
+        assert_eq!(distances[0][3], f32::INFINITY);
+        assert_eq!(distances[4][2], f32::INFINITY);
+
+        fill_unreachable(&mut distances);
+        assert_eq!(distances[0][3], 3.);
+        assert_eq!(distances[4][2], 3.);
+        assert_eq!(distances[0][2], 2.);
+        assert_eq!(distances[3][4], 1.);
+        assert!(distances.iter().flatten().all(|d| d.is_finite()));
+    }
+
+    #[test]
+    fn spectral_ring_keeps_neighbors_together() {
+        let n = 12;
+        let positions = spectral_layout(&cycle(n), &mut StdRng::seed_from_u64(0));
+
+        // the ring is laid out as a circle, so each node's two nearest are its graph neighbors
+        for i in 0..n {
+            let mut others: Vec<usize> = (0..n).filter(|&j| j != i).collect();
+            others.sort_by(|&a, &b| positions[i].distance(positions[a]).total_cmp(&positions[i].distance(positions[b])));
+            let mut nearest = [others[0], others[1]];
+            nearest.sort();
+            let mut neighbors = [(i + n - 1) % n, (i + 1) % n];
+            neighbors.sort();
+            assert_eq!(nearest, neighbors, "{:?}", positions);
+        }
+    }
+
+    #[test]
+    fn coarsening_preserves_connectivity() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut adjacent = grid(10, 10);
+        while adjacent.len() > MULTILEVEL_COARSEST_SIZE {
+            let (parent, coarse) = coarsen(&adjacent, &mut rng);
+            assert!(connected(&coarse));
+            // nodes are only merged with a neighbor, and at most in pairs
+            for p in 0..coarse.len() {
+                let merged: Vec<usize> = (0..adjacent.len()).filter(|&i| parent[i] == p).collect();
+                assert!(matches!(merged.len(), 1 | 2));
+                if let [a, b] = merged[..] {
+                    assert!(adjacent[a].contains(&b));
+                }
+            }
+            assert!(coarse.len() < adjacent.len());
+            adjacent = coarse;
+        }
+
+        let positions = multilevel_layout(&grid(10, 10), &mut rng);
+        assert_eq!(positions.len(), 100);
+        assert!(positions.iter().all(|p| p.is_finite()));
+    }
+}
@@ -1,25 +1,76 @@
 #![feature(iterator_try_collect)]
 #![windows_subsystem = "windows"]
-use bevy::{log::LogPlugin, prelude::*, window::{PresentMode, PrimaryWindow, WindowResolution}};
-use graph::{compute_disjoint_pairs, compute_neighbors, GraphSpawnConfig};
-use phases::{clear_dots, spawn_dots, test_transitions, Phases, SpawnMethod};
-use physics::{accel_dampen, apply_acceleration, apply_attraction_between_edges, apply_force_between_dots, apply_force_between_dots_and_walls, apply_velocity, vel_dampen, PhysicsConfig};
+use analytics::{update_graph_stats, GraphStats};
+use boundary::{enforce_boundary, Boundary};
+use bevy::{input::mouse::MouseWheel, log::LogPlugin, prelude::*, time::TimeSystem, window::{PresentMode, PrimaryWindow, WindowResolution}};
+use centrality::update_centrality;
+use community::{detect_communities, CommunityConfig, CommunityStats};
+use graph::{clear_edges, compute_disjoint_pairs, compute_neighbors, spawn_edges, GraphSpawnConfig};
+use labels::{spawn_labels, update_labels, LabelConfig};
+use layout::{initial_placement, stress_layout_incremental, stress_layout_one_shot, StressLayoutCache, StressLayoutConfig};
+use obstacles::{apply_force_fields, apply_obstacle_repulsion, resolve_obstacle_collisions, Obstacles};
+use pathfinding::{select_path_endpoints, update_path, PathSelection, PathfindingConfig};
+use phases::{clear_dots, spawn_dots, test_transitions, DotSpawnConfig, Phases, SpawnMethod};
+use physics::{accel_dampen, apply_acceleration, apply_attraction_between_edges, apply_community_attraction, apply_component_centering, apply_force_between_dots, apply_force_between_dots_and_walls, apply_gravity, apply_velocity, resolve_collisions, vel_dampen, PhysicsConfig};
+use quality::{measure_layout_quality, LayoutQualityConfig, LayoutQualityLog};
 use rand::{rngs::StdRng, SeedableRng};
-use render::{render_dots, render_graph_edges, render_partners};
-use bevy_egui::EguiPlugin;
-use ui::ui_tweak_panel;
+use record::{apply_deterministic_mode, deterministic, finish_recording, record_frame, recording, RecordConfig, Recorder};
+use replay::{begin_replay, end_replay_frame, not_scrubbing, play_replay_inputs, replaying, scrub_replay, Replay};
+use render::{attach_dot_meshes, render_dots, render_graph_edges, render_boundary, render_inspected_dot, render_obstacles, render_partners, render_path, setup_mesh_rendering, update_dot_mesh_styles, update_edge_mesh, update_mesh_visibility, using_gizmos, using_meshes, RenderConfig};
+use snapshot::{snapshot_requested, SnapshotRequests};
+use style::{apply_heatmap, apply_styles, load_style_config, Legend, StyleConfig};
+use bevy_egui::{EguiContexts, EguiPlugin};
+use export::{export_requested, ExportRequests};
+use headless::{run_headless, HeadlessArgs};
+use ui::{select_inspected_dot, ui_analytics_panel, ui_boundary_panel, ui_centrality_panel, ui_community_panel, ui_inspector_panel, ui_legend_panel, ui_obstacles_panel, ui_path_panel, ui_quality_panel, ui_output_panel, ui_replay_panel, ui_style_panel, ui_tweak_panel, Inspector};
 
 mod phases;
+mod analytics;
+mod boundary;
+mod centrality;
+mod community;
+mod export;
+mod geometry;
 mod graph;
+mod headless;
+mod labels;
+mod layout;
+mod obstacles;
+mod pathfinding;
 mod render;
 mod physics;
+mod preset;
+mod quality;
+mod record;
+mod replay;
+mod snapshot;
+mod style;
+mod sweep;
 mod ui;
 
 const WIN_SIZE: (f32, f32) = (1280.0, 720.0);
+/// Limits on the main camera's projection scale
+const MIN_ZOOM: f32 = 0.2;
+const MAX_ZOOM: f32 = 5.;
+/// How many times a second physics is stepped
+const FIXED_TIMESTEP_HZ: f64 = 60.;
+/// Seeds the random number generator, so every run spawns the same dots
+const SEED: u64 = 69;
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins
+    match HeadlessArgs::from_env() {
+        Ok(Some(args)) => run_headless(args),
+        Ok(None) => run_windowed(),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+fn run_windowed() {
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins
             .set(LogPlugin {
                 level: bevy::log::Level::DEBUG,
                 filter: "warn,profile_website=debug".into(),
@@ -35,39 +86,152 @@ fn main() {
                 ..Default::default()
             })
         )
-        .add_plugins(EguiPlugin)
-        .insert_state(Phases::Init)
-        .insert_resource(MousePosition(Vec2::ZERO))
-        .insert_resource(Randomness(StdRng::seed_from_u64(69)))
+        .add_plugins(EguiPlugin);
+    add_simulation(&mut app);
+    app.insert_resource(MousePosition(Vec2::ZERO))
+        .insert_resource(LabelConfig::default())
+        .insert_resource(ExportRequests::default())
+        .insert_resource(PathfindingConfig::default())
+        .insert_resource(PathSelection::default())
+        .insert_resource(Inspector::default())
+        .add_systems(Startup, (startup, setup_mesh_rendering))
+        // Always run inside phase
+        .add_systems(Update, render_dots.run_if(in_state(Phases::JustDots)).run_if(using_gizmos))
+        .add_systems(Update, (render_dots, render_graph_edges).run_if(in_state(Phases::Graph)).run_if(using_gizmos))
+        .add_systems(Update, update_edge_mesh.run_if(in_state(Phases::Graph)).run_if(using_meshes))
+        .add_systems(Update, render_partners.run_if(in_state(Phases::DisconnectedEdges)))
+        .add_systems(Update, (
+            select_path_endpoints.after(update_mouse),
+            update_path.after(select_path_endpoints),
+            render_path.after(update_path),
+        ).run_if(in_state(Phases::Graph)))
+        // Always run
+        .add_systems(Update, (
+            update_mouse.run_if(not(replaying)),
+            zoom_camera,
+            ui_tweak_panel,
+            ui_style_panel,
+            ui_output_panel,
+            ui_replay_panel,
+            ui_analytics_panel,
+            ui_path_panel,
+            ui_community_panel,
+            ui_centrality_panel,
+            ui_quality_panel,
+            ui_inspector_panel,
+            ui_boundary_panel,
+            ui_obstacles_panel,
+            select_inspected_dot.after(update_mouse),
+            render_inspected_dot,
+            render_boundary,
+            render_obstacles,
+        ))
+        // Rendering
+        .add_systems(Update, (
+            attach_dot_meshes,
+            update_mesh_visibility,
+            update_dot_mesh_styles.after(apply_heatmap),
+            ui_legend_panel,
+            spawn_labels,
+            update_labels.after(spawn_labels),
+        ))
+        .add_systems(Update, apply_deterministic_mode.run_if(resource_changed::<RecordConfig>))
+        .add_systems(Last, export_requested)
+        .run();
+}
+
+/// Everything needed to run the simulation, shared by the windowed and headless apps
+fn add_simulation(app: &mut App) {
+    app.insert_state(Phases::Init)
+        .insert_resource(Randomness(StdRng::seed_from_u64(SEED)))
         .insert_resource(PhysicsConfig::default())
         .insert_resource(SpawnMethod::Random)
+        .insert_resource(DotSpawnConfig::default())
         .insert_resource(GraphSpawnConfig::default())
-        .add_systems(Startup, startup)
+        .insert_resource(StressLayoutConfig::default())
+        .insert_resource(RenderConfig::default())
+        .insert_resource(StyleConfig::default())
+        .insert_resource(Legend::default())
+        .insert_resource(StressLayoutCache::default())
+        .insert_resource(RecordConfig::default())
+        .insert_resource(Recorder::default())
+        .insert_resource(Replay::default())
+        .insert_resource(SnapshotRequests::default())
+        .insert_resource(GraphStats::default())
+        .insert_resource(CommunityConfig::default())
+        .insert_resource(CommunityStats::default())
+        .insert_resource(LayoutQualityConfig::default())
+        .insert_resource(LayoutQualityLog::default())
+        .insert_resource(Boundary::default())
+        .insert_resource(Obstacles::default())
+        .insert_resource(Time::<Fixed>::from_hz(FIXED_TIMESTEP_HZ))
+        .add_systems(Startup, load_style_config)
         // Phase transitions
         .add_systems(OnEnter(Phases::Init), (
             clear_dots,
+            clear_edges,
             spawn_dots.after(clear_dots),
         ))
-        .add_systems(OnEnter(Phases::Graph), compute_neighbors)
+        .add_systems(OnEnter(Phases::Graph), (
+            compute_neighbors,
+            initial_placement.after(compute_neighbors),
+            stress_layout_one_shot.after(initial_placement),
+            spawn_edges.after(compute_neighbors),
+        ))
         .add_systems(OnEnter(Phases::DisconnectedEdges), compute_disjoint_pairs)
-        // Always run inside phase
-        .add_systems(Update, render_dots.run_if(in_state(Phases::JustDots)))
-        .add_systems(Update, (render_dots, render_graph_edges).run_if(in_state(Phases::Graph)))
-        .add_systems(Update, render_partners.run_if(in_state(Phases::DisconnectedEdges)))
-        // Always run
+        .add_systems(Update, stress_layout_incremental.run_if(in_state(Phases::Graph)).run_if(not_scrubbing))
         .add_systems(Update, (
-            update_mouse,
             test_transitions,
+            apply_heatmap.after(apply_styles),
+            update_graph_stats,
+            detect_communities,
+            update_centrality,
+            measure_layout_quality.after(apply_styles),
+            apply_styles.after(detect_communities).after(update_centrality),
+        ))
+        // Physics runs every frame. Collisions and walls move Dots back into place once they
+        // have moved
+        .add_systems(Update, (
             apply_acceleration,
             apply_velocity,
             apply_force_between_dots,
             apply_attraction_between_edges,
             vel_dampen,
             accel_dampen,
-            ui_tweak_panel,
-            apply_force_between_dots_and_walls
-        ))
-        .run();
+            apply_force_between_dots_and_walls,
+            apply_obstacle_repulsion,
+            apply_force_fields,
+            apply_gravity,
+            apply_component_centering,
+            apply_community_attraction,
+            (resolve_collisions, resolve_obstacle_collisions, enforce_boundary).chain().after(apply_velocity),
+        ).run_if(not(deterministic)).run_if(not_scrubbing))
+        // In deterministic mode physics runs on the fixed timestep instead, so it doesn't depend on
+        // the frame rate, and in a fixed order so that runs can be reproduced exactly
+        .add_systems(FixedUpdate, (
+            apply_force_between_dots,
+            apply_attraction_between_edges,
+            apply_force_between_dots_and_walls,
+            apply_obstacle_repulsion,
+            apply_force_fields,
+            apply_gravity,
+            apply_component_centering,
+            apply_community_attraction,
+            accel_dampen,
+            apply_acceleration,
+            vel_dampen,
+            apply_velocity,
+            resolve_collisions,
+            resolve_obstacle_collisions,
+            enforce_boundary,
+        ).chain().run_if(deterministic))
+        .add_systems(FixedPostUpdate, record_frame.run_if(recording))
+        .add_systems(Last, finish_recording)
+        // Replays start and feed in inputs once the frame's time step is known, and record or check
+        // what happened once everything else has run
+        .add_systems(First, (begin_replay, play_replay_inputs).chain().after(TimeSystem))
+        .add_systems(Last, (end_replay_frame, scrub_replay).chain())
+        .add_systems(Last, snapshot_requested);
 }
 
 fn startup(
@@ -99,15 +263,38 @@ struct Randomness(StdRng);
 
 fn update_mouse(
     mut mouse: ResMut<MousePosition>,
-    window_q: Query<&Window, With<PrimaryWindow>>
+    window_q: Query<&Window, With<PrimaryWindow>>,
+    camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
 ) {
     let window = window_q.single();
+    let (camera, camera_transform) = camera_q.single();
     if let Some(world_position) = window.cursor_position()
+        .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor))
     {
         mouse.0 = world_position;
     }
 }
 
+/// Zooms the main camera in and out around the middle of the window with the scroll wheel
+fn zoom_camera(
+    mut scroll_events: EventReader<MouseWheel>,
+    mut camera_q: Query<&mut OrthographicProjection, With<MainCamera>>,
+    mut contexts: EguiContexts,
+) {
+    // don't zoom while scrolling through the UI
+    if contexts.ctx_mut().wants_pointer_input() {
+        scroll_events.clear();
+        return;
+    }
+
+    let scroll: f32 = scroll_events.read().map(|event| event.y).sum();
+    if scroll == 0. {
+        return;
+    }
+    let mut projection = camera_q.single_mut();
+    projection.scale = (projection.scale * 0.9f32.powf(scroll)).clamp(MIN_ZOOM, MAX_ZOOM);
+}
+
 /*
 // phase 1 (just dots)
 1. Dots spawn randomly on load
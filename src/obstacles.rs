@@ -0,0 +1,226 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{boundary::{BoundaryShape, MIN_WALL_DISTANCE}, graph::{Dot, Neighbors}, physics::{Acceleration, Mass, PhysicsConfig, Velocity}};
+
+/// How big obstacles and fields are when they're first added
+const OBSTACLE_SIZE: f32 = 40.;
+const FIELD_RADIUS: f32 = 150.;
+const FIELD_STRENGTH: f32 = 0.5;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ObstacleBehaviour {
+    /// Dots bounce off the outline, like they do off each other when colliding
+    Collide,
+    /// The outline pushes Dots away like a wall, and Dots inside are pushed straight out
+    Repel,
+}
+
+/// A static shape Dots are kept out of
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Obstacle {
+    pub shape: BoundaryShape,
+    pub behaviour: ObstacleBehaviour,
+}
+
+impl Obstacle {
+    pub fn circle(center: Vec2) -> Self {
+        Obstacle {
+            shape: BoundaryShape::Circle { center, radius: OBSTACLE_SIZE },
+            behaviour: ObstacleBehaviour::Collide,
+        }
+    }
+
+    pub fn rectangle(center: Vec2) -> Self {
+        Obstacle {
+            shape: BoundaryShape::Rectangle { min: center - OBSTACLE_SIZE, max: center + OBSTACLE_SIZE },
+            behaviour: ObstacleBehaviour::Collide,
+        }
+    }
+
+    /// A triangle
+    pub fn polygon(center: Vec2) -> Self {
+        let vertices = (0..3)
+            .map(|i| center + Vec2::from_angle(i as f32 / 3. * std::f32::consts::TAU) * OBSTACLE_SIZE)
+            .collect();
+        Obstacle {
+            shape: BoundaryShape::Polygon { vertices },
+            behaviour: ObstacleBehaviour::Collide,
+        }
+    }
+
+    /// The closest point on the outline to `p`, the direction out of the obstacle there, and how
+    /// far `p` is outside it (negative when inside)
+    fn surface(&self, p: Vec2) -> Option<(Vec2, Vec2, f32)> {
+        if !self.shape.is_closed() {
+            return None;
+        }
+        let closest = self.shape.closest_point(p)?;
+        let distance = p.distance(closest);
+        if self.shape.contains(p) {
+            Some((closest, (closest - p).normalize_or_zero(), -distance))
+        } else {
+            Some((closest, (p - closest).normalize_or_zero(), distance))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FieldFalloff {
+    /// Just as strong anywhere inside the radius
+    Constant,
+    /// Fades to nothing at the radius
+    Linear,
+    /// Fades to nothing at the radius, and stays strong only close to the centre
+    Quadratic,
+}
+
+/// A point that pulls in or pushes away every Dot within its radius
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForceField {
+    pub center: Vec2,
+    pub radius: f32,
+    /// Positive attracts and negative repels
+    pub strength: f32,
+    pub falloff: FieldFalloff,
+}
+
+impl ForceField {
+    pub fn new(center: Vec2) -> Self {
+        ForceField {
+            center,
+            radius: FIELD_RADIUS,
+            strength: FIELD_STRENGTH,
+            falloff: FieldFalloff::Linear,
+        }
+    }
+
+    /// The acceleration towards the centre of a Dot at `p`
+    pub fn pull(&self, p: Vec2) -> Vec2 {
+        let to_center = self.center - p;
+        let distance = to_center.length();
+        if distance >= self.radius {
+            return Vec2::ZERO;
+        }
+        let remaining = 1. - distance / self.radius;
+        let scale = match self.falloff {
+            FieldFalloff::Constant => 1.,
+            FieldFalloff::Linear => remaining,
+            FieldFalloff::Quadratic => remaining * remaining,
+        };
+        to_center.normalize_or_zero() * self.strength * scale
+    }
+}
+
+/// Everything placed in the scene for Dots to move around
+#[derive(Resource, Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Obstacles {
+    pub obstacles: Vec<Obstacle>,
+    pub fields: Vec<ForceField>,
+}
+
+/// Pushes Dots away from obstacles that repel, as hard as the walls do
+pub fn apply_obstacle_repulsion(
+    mut q: Query<(&mut Acceleration, &Transform, &Mass, &Neighbors), With<Dot>>,
+    physics_config: Res<PhysicsConfig>,
+    obstacles: Res<Obstacles>,
+) {
+    let repelling: Vec<&Obstacle> = obstacles.obstacles.iter().filter(|o| o.behaviour == ObstacleBehaviour::Repel).collect();
+    if repelling.is_empty() {
+        return;
+    }
+
+    for (mut accel, tf, mass, neighbors) in q.iter_mut() {
+        let p = tf.translation.xy();
+        let force: Vec2 = repelling.iter()
+            .filter_map(|obstacle| obstacle.surface(p))
+            .map(|(_, outwards, distance)| {
+                // anything inside is as close to the outline as it can be
+                outwards * physics_config.wall_repel_strength / distance.max(MIN_WALL_DISTANCE).powi(2)
+            })
+            .sum();
+        accel.0 += (force / physics_config.dot_mass(mass, neighbors)).extend(0.);
+    }
+}
+
+/// Pulls Dots towards attractors and pushes them away from repulsors. Mass is ignored, as it is by
+/// `apply_gravity`
+pub fn apply_force_fields(
+    mut q: Query<(&mut Acceleration, &Transform), With<Dot>>,
+    obstacles: Res<Obstacles>,
+) {
+    if obstacles.fields.is_empty() {
+        return;
+    }
+
+    for (mut accel, tf) in q.iter_mut() {
+        let p = tf.translation.xy();
+        let pull: Vec2 = obstacles.fields.iter().map(|field| field.pull(p)).sum();
+        accel.0 += pull.extend(0.);
+    }
+}
+
+/// Moves Dots that overlap a solid obstacle back out until they just touch it, and bounces them off
+/// it. Dots are as big as they are when colliding with each other
+pub fn resolve_obstacle_collisions(
+    mut q: Query<(&mut Transform, &mut Velocity), With<Dot>>,
+    physics_config: Res<PhysicsConfig>,
+    obstacles: Res<Obstacles>,
+) {
+    let solid: Vec<&Obstacle> = obstacles.obstacles.iter().filter(|o| o.behaviour == ObstacleBehaviour::Collide).collect();
+    if solid.is_empty() {
+        return;
+    }
+
+    let radius = physics_config.collision_radius;
+    for (mut tf, mut vel) in q.iter_mut() {
+        let mut pos = tf.translation.xy();
+        let mut v = vel.0.xy();
+        for obstacle in solid.iter() {
+            let Some((closest, outwards, distance)) = obstacle.surface(pos) else { continue };
+            if distance >= radius {
+                continue;
+            }
+            pos = closest + outwards * radius;
+            let inwards_speed = v.dot(outwards).min(0.);
+            v -= outwards * inwards_speed * (1. + physics_config.restitution);
+        }
+
+        if pos != tf.translation.xy() {
+            tf.translation = pos.extend(tf.translation.z);
+            vel.0 = v.extend(vel.0.z);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    #[test]
+    fn dots_inside_solid_obstacles_are_pushed_out() {
+        let obstacles = Obstacles {
+            obstacles: vec![Obstacle::rectangle(Vec2::ZERO), Obstacle::polygon(Vec2::new(200., 0.))],
+            fields: Vec::new(),
+        };
+        let mut world = World::new();
+        world.insert_resource(PhysicsConfig::default());
+        world.insert_resource(obstacles.clone());
+        for pos in [Vec2::new(5., 3.), Vec2::new(-38., 39.), Vec2::new(205., 0.)] {
+            world.spawn((Dot, Transform::from_translation(pos.extend(0.)), Velocity(Vec3::new(1., 1., 0.))));
+        }
+
+        world.run_system_once(resolve_obstacle_collisions);
+
+        let radius = PhysicsConfig::default().collision_radius;
+        for tf in world.query_filtered::<&Transform, With<Dot>>().iter(&world) {
+            let pos = tf.translation.xy();
+            for obstacle in obstacles.obstacles.iter() {
+                let (_, _, distance) = obstacle.surface(pos).unwrap();
+                assert!(distance >= radius - 0.001, "{:?} is {} from {:?}", pos, distance, obstacle.shape);
+            }
+        }
+    }
+}
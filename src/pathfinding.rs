@@ -0,0 +1,335 @@
+use std::{cmp::Ordering, collections::{BinaryHeap, VecDeque}};
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::EguiContexts;
+use ordered_float::NotNan;
+
+use crate::{graph::{Adjacency, Dot, Edge, Neighbors}, style::DotStyle, MousePosition};
+
+/// How close a click has to be to a Dot, beyond its radius, to select it
+const PICK_DISTANCE: f32 = 6.;
+/// How long the wavefront takes to sweep out from the start
+const WAVEFRONT_SECONDS: f32 = 2.;
+/// How often a path costed by length is searched again, since lengths change as Dots move
+const PATH_REFRESH_SECONDS: f32 = 0.5;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PathAlgorithm {
+    /// Fewest hops, expanding one layer of neighbors at a time
+    BreadthFirst,
+    Dijkstra,
+    /// Dijkstra guided towards the goal by straight-line distance
+    AStar,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PathCost {
+    /// The distance between the Dots
+    Length,
+    /// The weight of the Edge between them, or 1 if there isn't one
+    Weight,
+}
+
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct PathfindingConfig {
+    /// Clicking Dots picks the start and goal of a path
+    pub enabled: bool,
+    pub algorithm: PathAlgorithm,
+    /// Used by Dijkstra and A*
+    pub cost: PathCost,
+    pub wavefront_seconds: f32,
+}
+
+impl Default for PathfindingConfig {
+    fn default() -> Self {
+        PathfindingConfig {
+            enabled: false,
+            algorithm: PathAlgorithm::BreadthFirst,
+            cost: PathCost::Length,
+            wavefront_seconds: WAVEFRONT_SECONDS,
+        }
+    }
+}
+
+/// The Dots picked as the ends of a path, and the result of searching between them
+#[derive(Resource, Default)]
+pub struct PathSelection {
+    pub start: Option<Entity>,
+    pub goal: Option<Entity>,
+    /// From start to goal, empty if there is no path
+    pub path: Vec<Entity>,
+    /// The Dots in the order they were reached by the search, one group per step
+    pub expansion: Vec<Vec<Entity>>,
+    /// When the search was run, to animate its expansion
+    pub searched_at: f32,
+    /// When the path was last searched for again, which doesn't restart the animation
+    pub refreshed_at: f32,
+}
+
+/// The outcome of a search, in terms of node indices
+pub struct Traversal {
+    pub path: Option<Vec<usize>>,
+    pub expansion: Vec<Vec<usize>>,
+}
+
+fn trace_path(parent: &[Option<usize>], start: usize, goal: usize) -> Option<Vec<usize>> {
+    let mut path = vec![goal];
+    let mut current = goal;
+    while current != start {
+        current = parent[current]?;
+        path.push(current);
+    }
+    path.reverse();
+    Some(path)
+}
+
+/// Expands outwards from `start` one layer at a time until `goal` is reached. Every layer is one
+/// hop further from the start than the last.
+pub fn breadth_first(adjacent: &[Vec<usize>], start: usize, goal: usize) -> Traversal {
+    let mut parent = vec![None; adjacent.len()];
+    let mut seen = vec![false; adjacent.len()];
+    let mut expansion = vec![vec![start]];
+    seen[start] = true;
+
+    let mut queue = VecDeque::from([(start, 0)]);
+    while let Some((current, depth)) = queue.pop_front() {
+        if current == goal {
+            break;
+        }
+        for &next in adjacent[current].iter() {
+            if !seen[next] {
+                seen[next] = true;
+                parent[next] = Some(current);
+                queue.push_back((next, depth + 1));
+                if expansion.len() <= depth + 1 {
+                    expansion.push(Vec::new());
+                }
+                expansion[depth + 1].push(next);
+            }
+        }
+    }
+
+    Traversal { path: trace_path(&parent, start, goal), expansion }
+}
+
+#[derive(PartialEq, Eq)]
+struct OpenEntry {
+    estimate: NotNan<f32>,
+    node: usize,
+}
+
+impl Ord for OpenEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed, so the heap pops the lowest estimate first
+        other.estimate.cmp(&self.estimate).then_with(|| other.node.cmp(&self.node))
+    }
+}
+
+impl PartialOrd for OpenEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A* search with non-negative edge costs. With a heuristic of zero this is Dijkstra's algorithm.
+/// Every node is its own step of the expansion, in the order it was settled.
+pub fn best_first(
+    adjacent: &[Vec<usize>],
+    start: usize,
+    goal: usize,
+    cost: impl Fn(usize, usize) -> f32,
+    heuristic: impl Fn(usize) -> f32,
+) -> Traversal {
+    let mut distance = vec![f32::INFINITY; adjacent.len()];
+    let mut parent = vec![None; adjacent.len()];
+    let mut settled = vec![false; adjacent.len()];
+    let mut expansion = Vec::new();
+
+    let not_nan = |x: f32| NotNan::new(x).unwrap_or(NotNan::new(f32::INFINITY).unwrap());
+
+    distance[start] = 0.;
+    let mut open = BinaryHeap::from([OpenEntry { estimate: not_nan(heuristic(start)), node: start }]);
+    while let Some(OpenEntry { node: current, .. }) = open.pop() {
+        if settled[current] {
+            continue;
+        }
+        settled[current] = true;
+        expansion.push(vec![current]);
+        if current == goal {
+            break;
+        }
+
+        for &next in adjacent[current].iter() {
+            let d = distance[current] + cost(current, next).max(0.);
+            if d < distance[next] {
+                distance[next] = d;
+                parent[next] = Some(current);
+                open.push(OpenEntry { estimate: not_nan(d + heuristic(next)), node: next });
+            }
+        }
+    }
+
+    Traversal { path: trace_path(&parent, start, goal), expansion }
+}
+
+/// The Dot nearest to `pos`, if `pos` is on it or close enough
+pub fn dot_at<'a>(dots: impl Iterator<Item = (Entity, &'a Transform, &'a DotStyle)>, pos: Vec2) -> Option<Entity> {
+    dots.map(|(eid, tf, style)| (eid, tf.translation.xy().distance(pos) - style.radius))
+        .filter(|(_, distance)| *distance <= PICK_DISTANCE)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(eid, _)| eid)
+}
+
+/// While enabled, clicking a Dot makes it the start of a path, and clicking another makes it the
+/// goal. Clicking again starts over.
+pub fn select_path_endpoints(
+    dots_q: Query<(Entity, &Transform, &DotStyle), With<Dot>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    mouse: Res<MousePosition>,
+    config: Res<PathfindingConfig>,
+    mut selection: ResMut<PathSelection>,
+    mut contexts: EguiContexts,
+) {
+    if !config.enabled || !buttons.just_pressed(MouseButton::Left) || contexts.ctx_mut().wants_pointer_input() {
+        return;
+    }
+
+    let Some(clicked) = dot_at(dots_q.iter(), mouse.0) else { return };
+
+    match (selection.start, selection.goal) {
+        (Some(_), None) => selection.goal = Some(clicked),
+        _ => {
+            selection.start = Some(clicked);
+            selection.goal = None;
+        }
+    }
+}
+
+/// Searches for a path whenever the ends, the algorithm or the graph change, and every so often
+/// while it's costed by the lengths of edges
+pub fn update_path(
+    dots_q: Query<(Entity, &Neighbors, &Transform), With<Dot>>,
+    edges_q: Query<&Edge>,
+    changed: Query<(), Changed<Neighbors>>,
+    config: Res<PathfindingConfig>,
+    mut selection: ResMut<PathSelection>,
+    time: Res<Time>,
+) {
+    let new_search = selection.is_changed() || config.is_changed() || !changed.is_empty();
+    let stale = config.cost == PathCost::Length
+        && config.algorithm != PathAlgorithm::BreadthFirst
+        && time.elapsed_seconds() - selection.refreshed_at >= PATH_REFRESH_SECONDS;
+    if !new_search && !stale {
+        return;
+    }
+
+    let (Some(start), Some(goal)) = (selection.start, selection.goal) else {
+        if !selection.path.is_empty() || !selection.expansion.is_empty() {
+            selection.path.clear();
+            selection.expansion.clear();
+        }
+        return;
+    };
+
+    let adjacency = Adjacency::from_neighbors(dots_q.iter().map(|(eid, neighbors, _)| (eid, neighbors)));
+    let index_of: HashMap<Entity, usize> = adjacency.entities.iter().enumerate().map(|(i, eid)| (*eid, i)).collect();
+    let (Some(&start), Some(&goal)) = (index_of.get(&start), index_of.get(&goal)) else {
+        // one of the ends was despawned
+        selection.start = None;
+        selection.goal = None;
+        return;
+    };
+
+    let positions: Vec<Vec2> = adjacency.entities.iter()
+        .map(|eid| dots_q.get(*eid).map_or(Vec2::ZERO, |(_, _, tf)| tf.translation.xy()))
+        .collect();
+    let mut weights = HashMap::new();
+    for edge in edges_q.iter() {
+        if let (Some(&a), Some(&b)) = (index_of.get(&edge.source), index_of.get(&edge.target)) {
+            weights.insert((a.min(b), a.max(b)), edge.weight);
+        }
+    }
+
+    let length = |a: usize, b: usize| positions[a].distance(positions[b]);
+    let weight = |a: usize, b: usize| weights.get(&(a.min(b), a.max(b))).cloned().unwrap_or(1.);
+    let traversal = match (&config.algorithm, &config.cost) {
+        (PathAlgorithm::BreadthFirst, _) => breadth_first(&adjacency.adjacent, start, goal),
+        (PathAlgorithm::Dijkstra, PathCost::Length) => best_first(&adjacency.adjacent, start, goal, length, |_| 0.),
+        (PathAlgorithm::Dijkstra, PathCost::Weight) => best_first(&adjacency.adjacent, start, goal, weight, |_| 0.),
+        (PathAlgorithm::AStar, PathCost::Length) => {
+            best_first(&adjacency.adjacent, start, goal, length, |node| positions[node].distance(positions[goal]))
+        }
+        // straight-line distance says nothing about weights, so there is no useful heuristic
+        (PathAlgorithm::AStar, PathCost::Weight) => best_first(&adjacency.adjacent, start, goal, weight, |_| 0.),
+    };
+
+    let to_entities = |nodes: &[usize]| nodes.iter().map(|&i| adjacency.entities[i]).collect::<Vec<Entity>>();
+    let selection = selection.bypass_change_detection();
+    selection.path = traversal.path.as_deref().map(to_entities).unwrap_or_default();
+    selection.expansion = traversal.expansion.iter().map(|layer| to_entities(layer)).collect();
+    selection.refreshed_at = time.elapsed_seconds();
+    if new_search {
+        selection.searched_at = time.elapsed_seconds();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Undirected adjacency lists from a list of edges
+    fn graph(n: usize, edges: &[(usize, usize)]) -> Vec<Vec<usize>> {
+        let mut adjacent = vec![Vec::new(); n];
+        for &(a, b) in edges {
+            adjacent[a].push(b);
+            adjacent[b].push(a);
+        }
+        adjacent
+    }
+
+    /// A square 0-1-2-3 with a long way round to 3 through 4 and 5, plus a node on its own
+    fn square() -> (Vec<Vec<usize>>, Vec<Vec2>) {
+        let adjacent = graph(7, &[(0, 4), (4, 5), (5, 3), (0, 1), (1, 2), (2, 3)]);
+        let positions = vec![
+            Vec2::new(0., 0.),
+            Vec2::new(0., 10.),
+            Vec2::new(10., 10.),
+            Vec2::new(10., 0.),
+            Vec2::new(3., -1.),
+            Vec2::new(7., -1.),
+            Vec2::new(50., 50.),
+        ];
+        (adjacent, positions)
+    }
+
+    #[test]
+    fn breadth_first_finds_the_fewest_hops() {
+        let (adjacent, _) = square();
+        let traversal = breadth_first(&adjacent, 0, 2);
+        assert_eq!(traversal.path, Some(vec![0, 1, 2]));
+        assert_eq!(traversal.expansion[0], vec![0]);
+        let mut first_layer = traversal.expansion[1].clone();
+        first_layer.sort();
+        assert_eq!(first_layer, vec![1, 4]);
+    }
+
+    #[test]
+    fn dijkstra_and_a_star_agree_on_length() {
+        let (adjacent, positions) = square();
+        let length = |a: usize, b: usize| positions[a].distance(positions[b]);
+        let dijkstra = best_first(&adjacent, 0, 3, length, |_| 0.);
+        let a_star = best_first(&adjacent, 0, 3, length, |node| positions[node].distance(positions[3]));
+        // three hops under the square are shorter than three around it
+        assert_eq!(dijkstra.path, Some(vec![0, 4, 5, 3]));
+        assert_eq!(a_star.path, dijkstra.path);
+        // the heuristic keeps A* from settling the far side of the square
+        assert!(a_star.expansion.len() < dijkstra.expansion.len());
+    }
+
+    #[test]
+    fn unreachable_goals_have_no_path() {
+        let (adjacent, positions) = square();
+        assert_eq!(breadth_first(&adjacent, 0, 6).path, None);
+        assert_eq!(best_first(&adjacent, 0, 6, |a, b| positions[a].distance(positions[b]), |_| 0.).path, None);
+    }
+}
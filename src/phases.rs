@@ -1,12 +1,33 @@
+use std::{collections::BTreeMap, fs};
+
 use bevy::prelude::*;
-use rand::Rng;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
 
-use crate::{graph::{Dot, Neighbors, Partner}, physics::{Acceleration, Velocity}, Randomness, WIN_SIZE};
+use crate::{graph::{Dot, Neighbors, Partner}, physics::{Acceleration, Charge, Mass, Velocity}, style::DotStyle, Randomness, WIN_SIZE};
 
 pub const NUMBER_OF_DOTS: usize = 200;
 const SEPARATION_ON_GRID: f32 = 40.;
+const SEPARATION_ON_HEX: f32 = 40.;
+const RING_SPACING: f32 = 40.;
+/// How far from the window edges randomly placed dots are kept
+const SPAWN_MARGIN: f32 = 100.;
+/// The minimum distance between any two dots when using Poisson-disk sampling
+const POISSON_MIN_DISTANCE: f32 = 35.;
+/// How many candidates are tried around a sample before it is retired
+const POISSON_MAX_ATTEMPTS: usize = 30;
+/// A ring, shipped with the repository
+const IMAGE_MASK_PATH: &str = "assets/mask.png";
+/// Pixels brighter than this (from 0 to 1) are eligible spawn locations
+const IMAGE_MASK_BRIGHTNESS_THRESHOLD: f32 = 0.5;
+/// A list of named values for each Dot, in the order they're spawned. Nothing is imported if
+/// the file doesn't exist
+const DOT_ATTRIBUTES_PATH: &str = "attributes.ron";
+/// Imported attributes which set a Dot's `Mass` and `Charge` rather than just styling it
+const MASS_ATTRIBUTE: &str = "mass";
+const CHARGE_ATTRIBUTE: &str = "charge";
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, States)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, States, Serialize, Deserialize)]
 pub enum Phases {
     #[default]
     Init,
@@ -16,10 +37,52 @@ pub enum Phases {
     PointingSegments
 }
 
-#[derive(Resource, Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Resource, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum SpawnMethod {
     Grid,
     Random,
+    /// Spawned randomly, then placed using Laplacian eigenvectors once the graph is computed
+    Spectral,
+    /// Spawned randomly, then placed by coarsening and refining once the graph is computed
+    Multilevel,
+    /// Blue noise: random, but with a minimum distance between dots
+    PoissonDisk,
+    Hexagonal,
+    /// Concentric rings around the center of the window
+    Rings,
+    /// Random positions on the bright pixels of a PNG
+    ImageMask,
+}
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DotSpawnConfig {
+    pub poisson_min_distance: f32,
+    pub image_mask_path: String,
+    pub brightness_threshold: f32,
+    pub attributes_path: String,
+}
+
+impl Default for DotSpawnConfig {
+    fn default() -> Self {
+        DotSpawnConfig {
+            poisson_min_distance: POISSON_MIN_DISTANCE,
+            image_mask_path: IMAGE_MASK_PATH.into(),
+            brightness_threshold: IMAGE_MASK_BRIGHTNESS_THRESHOLD,
+            attributes_path: DOT_ATTRIBUTES_PATH.into(),
+        }
+    }
+}
+
+/// Named values imported for a Dot, which styles can be mapped from. A "mass" or "charge" also
+/// sets the Dot's `Mass` or `Charge`
+#[derive(Component, Debug, Clone, Default, PartialEq)]
+pub struct DotAttributes(pub BTreeMap<String, f32>);
+
+/// Reads the attributes of every Dot, in the order they're spawned
+pub fn load_dot_attributes(path: &str) -> Result<Vec<DotAttributes>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let attributes: Vec<BTreeMap<String, f32>> = ron::from_str(&contents).map_err(|e| e.to_string())?;
+    Ok(attributes.into_iter().map(DotAttributes).collect())
 }
 
 pub fn clear_dots(
@@ -32,61 +95,227 @@ pub fn clear_dots(
 }
 
 pub fn spawn_dots(
-    next_state: ResMut<NextState<Phases>>,
-    commands: Commands,
-    randomness: ResMut<Randomness>,
-    spawn_method: Res<SpawnMethod>,
-) {
-    match *spawn_method {
-        SpawnMethod::Grid => spawn_dots_grid(next_state, commands),
-        SpawnMethod::Random => spawn_dots_random(next_state, commands, randomness),
-    }
-}
-
-pub fn spawn_dots_random(
     mut next_state: ResMut<NextState<Phases>>,
     mut commands: Commands,
     mut randomness: ResMut<Randomness>,
+    spawn_method: Res<SpawnMethod>,
+    dot_spawn_config: Res<DotSpawnConfig>,
 ) {
-    for _ in 0..NUMBER_OF_DOTS {
-        let x = randomness.0.gen_range(100.0..WIN_SIZE.0-100.0);
-        let y = randomness.0.gen_range(100.0..WIN_SIZE.1-100.0);
+    let positions = match *spawn_method {
+        SpawnMethod::Grid => grid_positions(),
+        SpawnMethod::Random | SpawnMethod::Spectral | SpawnMethod::Multilevel => random_positions(&mut randomness.0),
+        SpawnMethod::PoissonDisk => poisson_disk_positions(&mut randomness.0, dot_spawn_config.poisson_min_distance),
+        SpawnMethod::Hexagonal => hexagonal_positions(),
+        SpawnMethod::Rings => ring_positions(),
+        SpawnMethod::ImageMask => match image_mask_positions(&mut randomness.0, &dot_spawn_config) {
+            Ok(positions) => positions,
+            Err(e) => {
+                warn!("could not spawn from image mask \"{}\": {}", dot_spawn_config.image_mask_path, e);
+                random_positions(&mut randomness.0)
+            }
+        },
+    };
+
+    let attributes = if std::path::Path::new(&dot_spawn_config.attributes_path).exists() {
+        load_dot_attributes(&dot_spawn_config.attributes_path).unwrap_or_else(|e| {
+            warn!("could not import dot attributes \"{}\": {}", dot_spawn_config.attributes_path, e);
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+
+    for (i, pos) in positions.into_iter().enumerate() {
+        let attributes = attributes.get(i).cloned().unwrap_or_default();
         commands.spawn((
             Dot,
-            Transform::from_xyz(x, y, 0.),
+            Transform::from_xyz(pos.x, pos.y, 0.),
             Neighbors { neighbors: Vec::new() },
             Partner { partner: None },
             Velocity(Vec3::ZERO),
             Acceleration(Vec3::ZERO),
+            attributes.0.get(MASS_ATTRIBUTE).map_or_else(Mass::default, |&mass| Mass(mass)),
+            attributes.0.get(CHARGE_ATTRIBUTE).map_or_else(Charge::default, |&charge| Charge(charge)),
+            DotStyle::default(),
+            attributes,
         ));
     }
 
     next_state.set(Phases::JustDots);
 }
 
-pub fn spawn_dots_grid(
-    mut next_state: ResMut<NextState<Phases>>,
-    mut commands: Commands,
-) {
-    let number_of_columns = (NUMBER_OF_DOTS as f32).sqrt() as usize;
-    let grid_start_x = WIN_SIZE.0 / 2. - (number_of_columns as f32) * SEPARATION_ON_GRID / 2.;
-    let grid_start_y = WIN_SIZE.1 / 2. - (number_of_columns as f32) * SEPARATION_ON_GRID / 2.;
-    for i in 0..NUMBER_OF_DOTS {
-        let grid_x = i % number_of_columns;
-        let grid_y = i / number_of_columns;
-        let x = grid_start_x + grid_x as f32 * SEPARATION_ON_GRID;
-        let y = grid_start_y + grid_y as f32 * SEPARATION_ON_GRID;
-        commands.spawn((
-            Dot,
-            Transform::from_xyz(x, y, 0.),
-            Neighbors { neighbors: Vec::new() },
-            Partner { partner: None },
-            Velocity(Vec3::ZERO),
-            Acceleration(Vec3::ZERO),
-        ));
+pub fn random_positions(rng: &mut StdRng) -> Vec<Vec2> {
+    (0..NUMBER_OF_DOTS)
+        .map(|_| Vec2::new(
+            rng.gen_range(SPAWN_MARGIN..WIN_SIZE.0-SPAWN_MARGIN),
+            rng.gen_range(SPAWN_MARGIN..WIN_SIZE.1-SPAWN_MARGIN),
+        ))
+        .collect()
+}
+
+/// Lays dots out in rows as close to square as possible, centered in the window. The last row
+/// may be partially filled.
+pub fn grid_positions() -> Vec<Vec2> {
+    let number_of_columns = (NUMBER_OF_DOTS as f32).sqrt().ceil() as usize;
+    let number_of_rows = NUMBER_OF_DOTS.div_ceil(number_of_columns);
+    let grid_start_x = WIN_SIZE.0 / 2. - (number_of_columns - 1) as f32 * SEPARATION_ON_GRID / 2.;
+    let grid_start_y = WIN_SIZE.1 / 2. - (number_of_rows - 1) as f32 * SEPARATION_ON_GRID / 2.;
+
+    (0..NUMBER_OF_DOTS)
+        .map(|i| {
+            let grid_x = i % number_of_columns;
+            let grid_y = i / number_of_columns;
+            Vec2::new(
+                grid_start_x + grid_x as f32 * SEPARATION_ON_GRID,
+                grid_start_y + grid_y as f32 * SEPARATION_ON_GRID,
+            )
+        })
+        .collect()
+}
+
+/// Blue noise sampling using Bridson's algorithm. Every dot is at least `min_distance` from every
+/// other dot. Fewer than `NUMBER_OF_DOTS` dots are returned if they don't all fit in the spawn
+/// area.
+pub fn poisson_disk_positions(rng: &mut StdRng, min_distance: f32) -> Vec<Vec2> {
+    let min = Vec2::splat(SPAWN_MARGIN);
+    let max = Vec2::new(WIN_SIZE.0, WIN_SIZE.1) - SPAWN_MARGIN;
+
+    // each background grid cell can hold at most one sample
+    let cell_size = min_distance / std::f32::consts::SQRT_2;
+    let grid_width = ((max.x - min.x) / cell_size).ceil() as usize + 1;
+    let grid_height = ((max.y - min.y) / cell_size).ceil() as usize + 1;
+    let mut grid: Vec<Option<usize>> = vec![None; grid_width * grid_height];
+    let cell_of = |p: Vec2| {
+        let c = ((p - min) / cell_size).as_uvec2();
+        (c.x as usize, c.y as usize)
+    };
+
+    let mut samples = Vec::new();
+    let mut active = Vec::new();
+
+    let first = Vec2::new(rng.gen_range(min.x..max.x), rng.gen_range(min.y..max.y));
+    let (cx, cy) = cell_of(first);
+    grid[cy * grid_width + cx] = Some(0);
+    samples.push(first);
+    active.push(0);
+
+    while !active.is_empty() {
+        let active_index = rng.gen_range(0..active.len());
+        let origin = samples[active[active_index]];
+
+        let mut found = false;
+        for _ in 0..POISSON_MAX_ATTEMPTS {
+            // candidate in the annulus between r and 2r
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            let radius = rng.gen_range(min_distance..2. * min_distance);
+            let candidate = origin + Vec2::from_angle(angle) * radius;
+            if candidate.x < min.x || candidate.y < min.y || candidate.x >= max.x || candidate.y >= max.y {
+                continue;
+            }
+
+            let (cx, cy) = cell_of(candidate);
+            let too_close = (cy.saturating_sub(2)..=(cy + 2).min(grid_height - 1))
+                .flat_map(|y| (cx.saturating_sub(2)..=(cx + 2).min(grid_width - 1)).map(move |x| (x, y)))
+                .filter_map(|(x, y)| grid[y * grid_width + x])
+                .any(|other| samples[other].distance_squared(candidate) < min_distance * min_distance);
+
+            if !too_close {
+                grid[cy * grid_width + cx] = Some(samples.len());
+                active.push(samples.len());
+                samples.push(candidate);
+                found = true;
+                break;
+            }
+        }
+
+        if !found {
+            active.swap_remove(active_index);
+        }
     }
 
-    next_state.set(Phases::JustDots);
+    // the samples grow outwards from the first one, so stopping early would leave them in a clump.
+    // Instead the whole area is filled and a random subset kept, which is still spread out
+    samples.shuffle(rng);
+    samples.truncate(NUMBER_OF_DOTS);
+    samples
+}
+
+/// Lays dots out on a triangular lattice (every dot has 6 equidistant neighbors), with every
+/// other row offset by half a column, centered in the window
+pub fn hexagonal_positions() -> Vec<Vec2> {
+    let row_separation = SEPARATION_ON_HEX * 3f32.sqrt() / 2.;
+    // make the lattice roughly as wide as it is tall
+    let number_of_columns = ((NUMBER_OF_DOTS as f32 * row_separation / SEPARATION_ON_HEX).sqrt().ceil() as usize).max(1);
+    let number_of_rows = NUMBER_OF_DOTS.div_ceil(number_of_columns);
+    let start_x = WIN_SIZE.0 / 2. - (number_of_columns as f32 - 0.5) * SEPARATION_ON_HEX / 2.;
+    let start_y = WIN_SIZE.1 / 2. - (number_of_rows - 1) as f32 * row_separation / 2.;
+
+    (0..NUMBER_OF_DOTS)
+        .map(|i| {
+            let column = i % number_of_columns;
+            let row = i / number_of_columns;
+            let offset = if row % 2 == 1 { SEPARATION_ON_HEX / 2. } else { 0. };
+            Vec2::new(
+                start_x + column as f32 * SEPARATION_ON_HEX + offset,
+                start_y + row as f32 * row_separation,
+            )
+        })
+        .collect()
+}
+
+/// Lays dots out on concentric rings around the center of the window, keeping roughly
+/// `RING_SPACING` between dots on a ring and between rings
+pub fn ring_positions() -> Vec<Vec2> {
+    let center = Vec2::new(WIN_SIZE.0 / 2., WIN_SIZE.1 / 2.);
+    let mut positions = vec![center];
+
+    let mut ring = 1;
+    while positions.len() < NUMBER_OF_DOTS {
+        let radius = ring as f32 * RING_SPACING;
+        let capacity = (std::f32::consts::TAU * radius / RING_SPACING).floor() as usize;
+        // the outermost ring spreads its remaining dots evenly instead of leaving a gap
+        let count = capacity.min(NUMBER_OF_DOTS - positions.len());
+        // stagger rings so that dots don't line up radially
+        let phase = ring as f32 * 0.5;
+        for k in 0..count {
+            let angle = phase + k as f32 / count as f32 * std::f32::consts::TAU;
+            positions.push(center + Vec2::from_angle(angle) * radius);
+        }
+        ring += 1;
+    }
+
+    positions
+}
+
+/// Samples dot positions from the bright pixels of an image, scaled to fit the window while
+/// keeping its aspect ratio
+pub fn image_mask_positions(rng: &mut StdRng, dot_spawn_config: &DotSpawnConfig) -> Result<Vec<Vec2>, String> {
+    let image = image::open(&dot_spawn_config.image_mask_path).map_err(|e| e.to_string())?.into_luma8();
+
+    let threshold = (dot_spawn_config.brightness_threshold * 255.) as u8;
+    let bright_pixels: Vec<(u32, u32)> = image.enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel.0[0] > threshold)
+        .map(|(x, y, _)| (x, y))
+        .collect();
+    if bright_pixels.is_empty() {
+        return Err("no pixels are brighter than the threshold".into());
+    }
+
+    let image_size = Vec2::new(image.width() as f32, image.height() as f32);
+    let available = Vec2::new(WIN_SIZE.0, WIN_SIZE.1) - 2. * SPAWN_MARGIN;
+    let scale = f32::min(available.x / image_size.x, available.y / image_size.y);
+    let offset = (Vec2::new(WIN_SIZE.0, WIN_SIZE.1) - image_size * scale) / 2.;
+
+    // image rows go top to bottom, which matches our coordinates
+    let positions = (0..NUMBER_OF_DOTS)
+        .map(|_| {
+            let (x, y) = bright_pixels[rng.gen_range(0..bright_pixels.len())];
+            let jitter = Vec2::new(rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+            offset + (Vec2::new(x as f32, y as f32) + jitter) * scale
+        })
+        .collect();
+
+    Ok(positions)
 }
 
 pub fn test_transitions(
@@ -106,4 +335,36 @@ pub fn test_transitions(
     // if t > 20. {
     //     next_state.set(Phases::Graph);
     // }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn in_window(positions: &[Vec2]) -> bool {
+        positions.iter().all(|p| p.x >= 0. && p.y >= 0. && p.x <= WIN_SIZE.0 && p.y <= WIN_SIZE.1)
+    }
+
+    #[test]
+    fn lattices_fill_the_window_with_every_dot() {
+        for positions in [grid_positions(), hexagonal_positions()] {
+            assert_eq!(positions.len(), NUMBER_OF_DOTS);
+            assert!(in_window(&positions));
+        }
+    }
+
+    #[test]
+    fn poisson_disk_dots_keep_their_distance() {
+        for seed in 0..5 {
+            let positions = poisson_disk_positions(&mut StdRng::seed_from_u64(seed), POISSON_MIN_DISTANCE);
+            assert_eq!(positions.len(), NUMBER_OF_DOTS);
+            assert!(in_window(&positions));
+            for (i, a) in positions.iter().enumerate() {
+                for b in positions[i + 1..].iter() {
+                    assert!(a.distance(*b) >= POISSON_MIN_DISTANCE, "{} and {} are too close", a, b);
+                }
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
 
-use crate::{graph::{Dot, Neighbors}, WIN_SIZE};
+use crate::{boundary::{wrapped_offset, Boundary, WallBehaviour}, community::Community, graph::{connected_components, Adjacency, Dot, Edge, Neighbors}};
 
 pub const REPEL_STRENGTH: f32 = 1000.;
 pub const SPRING_COEFFICIENT: f32 = 0.012;
@@ -10,18 +11,101 @@ pub const VEL_DAMPENING: f32 = 0.7;
 pub const VEL_CAP: f32 = 50.;
 pub const ACC_DAMPENING: f32 = 0.85;
 pub const ACC_CAP: f32 = 10.;
+pub const COMMUNITY_ATTRACTION: f32 = 0.;
+pub const DOT_MASS: f32 = 1.;
+pub const DOT_CHARGE: f32 = 1.;
+/// Keeps a Dot's mass positive however it's scaled, so forces never divide by zero
+const MIN_MASS: f32 = 0.01;
+pub const COLLISION_RADIUS: f32 = 4.;
+pub const RESTITUTION: f32 = 0.5;
+/// The most times overlaps are resolved per step, since pushing one pair apart can push another
+/// together
+const COLLISION_MAX_ITERATIONS: usize = 50;
+/// How far past touching overlapping Dots are pushed, so rounding doesn't leave them overlapping
+const COLLISION_SLOP: f32 = 0.01;
+
+/// How the pull towards the centre changes with distance from it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GravityFalloff {
+    /// Grows with the distance, like a spring
+    #[default]
+    Linear,
+    /// The same everywhere
+    Constant,
+}
+
+/// How an edge's weight scales a per-edge spring parameter, or a Dot's degree a per-dot one
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WeightFunction {
+    /// Ignore the weight
+    #[default]
+    Constant,
+    Proportional,
+    Inverse,
+    /// Grows with the weight, but slowly. A weight of 1 leaves the parameter unchanged
+    Logarithmic,
+}
+
+impl WeightFunction {
+    pub fn apply(&self, base: f32, weight: f32) -> f32 {
+        match self {
+            WeightFunction::Constant => base,
+            WeightFunction::Proportional => base * weight,
+            WeightFunction::Inverse => base / weight.max(f32::EPSILON),
+            WeightFunction::Logarithmic => base * (1. + weight.max(0.).ln_1p() - 2f32.ln()),
+        }
+    }
+}
 
 // Runtime configuration for above starting constants
-#[derive(Resource)]
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PhysicsConfig {
     pub repel_strength: f32,
     pub spring_coefficient: f32,
     pub spring_resting_length: f32,
+    pub spring_coefficient_by_weight: WeightFunction,
+    pub spring_resting_length_by_weight: WeightFunction,
     pub wall_repel_strength: f32,
     pub vel_dampening: f32,
     pub vel_cap: f32,
     pub acc_dampening: f32,
     pub acc_cap: f32,
+    /// How strongly Dots are pulled towards the middle of their community
+    #[serde(default)]
+    pub community_attraction: f32,
+    /// Stop Dots overlapping by pushing them apart and bouncing them off each other
+    #[serde(default)]
+    pub collisions: bool,
+    #[serde(default = "default_collision_radius")]
+    pub collision_radius: f32,
+    /// How much of their approach speed colliding Dots keep, from 0 (none) to 1 (all of it)
+    #[serde(default = "default_restitution")]
+    pub restitution: f32,
+    /// How a Dot's degree scales its `Mass`
+    #[serde(default)]
+    pub mass_by_degree: WeightFunction,
+    /// How a Dot's degree scales its `Charge`
+    #[serde(default)]
+    pub charge_by_degree: WeightFunction,
+    /// How strongly every Dot is pulled towards the centre of the window
+    #[serde(default)]
+    pub center_gravity: f32,
+    #[serde(default)]
+    pub center_gravity_falloff: GravityFalloff,
+    /// Pulls every Dot the same way, like gravity does
+    #[serde(default)]
+    pub gravity: Vec2,
+    /// How strongly every connected component is pulled, as a whole, towards the centre
+    #[serde(default)]
+    pub component_centering: f32,
+}
+
+fn default_collision_radius() -> f32 {
+    COLLISION_RADIUS
+}
+
+fn default_restitution() -> f32 {
+    RESTITUTION
 }
 
 impl Default for PhysicsConfig {
@@ -30,15 +114,57 @@ impl Default for PhysicsConfig {
             repel_strength: REPEL_STRENGTH,
             spring_coefficient: SPRING_COEFFICIENT,
             spring_resting_length: SPRING_RESTING_LENGTH,
+            spring_coefficient_by_weight: WeightFunction::Constant,
+            spring_resting_length_by_weight: WeightFunction::Constant,
             wall_repel_strength: WALL_REPEL_STRENGTH,
             vel_dampening: VEL_DAMPENING,
             vel_cap: VEL_CAP,
             acc_dampening: ACC_DAMPENING,
             acc_cap: ACC_CAP,
+            community_attraction: COMMUNITY_ATTRACTION,
+            collisions: false,
+            collision_radius: COLLISION_RADIUS,
+            restitution: RESTITUTION,
+            mass_by_degree: WeightFunction::Constant,
+            charge_by_degree: WeightFunction::Constant,
+            center_gravity: 0.,
+            center_gravity_falloff: GravityFalloff::Linear,
+            gravity: Vec2::ZERO,
+            component_centering: 0.,
         }
     }
 }
 
+impl PhysicsConfig {
+    /// The resting length of an edge's spring, from its override or else its weight
+    pub fn edge_resting_length(&self, edge: &Edge) -> f32 {
+        edge.rest_length.unwrap_or_else(|| self.spring_resting_length_by_weight.apply(self.spring_resting_length, edge.weight))
+    }
+
+    /// The stiffness of an edge's spring, from its override or else its weight
+    pub fn edge_spring_coefficient(&self, edge: &Edge) -> f32 {
+        edge.stiffness.unwrap_or_else(|| self.spring_coefficient_by_weight.apply(self.spring_coefficient, edge.weight))
+    }
+
+    /// A Dot's mass, scaled by its degree
+    pub fn dot_mass(&self, mass: &Mass, neighbors: &Neighbors) -> f32 {
+        scale_by_degree(&self.mass_by_degree, mass.0, neighbors).max(MIN_MASS)
+    }
+
+    /// A Dot's charge, scaled by its degree
+    pub fn dot_charge(&self, charge: &Charge, neighbors: &Neighbors) -> f32 {
+        scale_by_degree(&self.charge_by_degree, charge.0, neighbors)
+    }
+}
+
+/// Dots without neighbors, like every Dot before the graph is built, keep the base value instead
+/// of having it multiplied by zero or divided by it
+fn scale_by_degree(weight_function: &WeightFunction, base: f32, neighbors: &Neighbors) -> f32 {
+    match neighbors.neighbors.len() {
+        0 => base,
+        degree => weight_function.apply(base, degree as f32),
+    }
+}
 
 #[derive(Component)]
 pub struct Velocity(pub Vec3);
@@ -46,6 +172,29 @@ pub struct Velocity(pub Vec3);
 #[derive(Component)]
 pub struct Acceleration(pub Vec3);
 
+/// How much a Dot resists being accelerated by forces
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Mass(pub f32);
+
+impl Default for Mass {
+    fn default() -> Self {
+        Mass(DOT_MASS)
+    }
+}
+
+/// How strongly a Dot repels and is repelled by other Dots
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Charge(pub f32);
+
+impl Default for Charge {
+    fn default() -> Self {
+        Charge(DOT_CHARGE)
+    }
+}
+
+/// A Dot as forces see it
+type MassiveDot<'a> = (&'a mut Acceleration, &'a Transform, &'a Mass, &'a Neighbors);
+
 pub fn apply_velocity(
     mut q: Query<(&mut Transform, &Velocity)>,
     time: Res<Time>
@@ -65,61 +214,145 @@ pub fn apply_acceleration(
 }
 
 pub fn apply_force_between_dots(
-    mut q: Query<(&mut Acceleration, &Transform), With<Dot>>,
+    mut q: Query<(&mut Acceleration, &Transform, &Mass, &Charge, &Neighbors), With<Dot>>,
     physics_config: Res<PhysicsConfig>,
+    boundary: Res<Boundary>,
 ) {
-    let mut iter = q.iter_combinations_mut();
-    while let Some([(mut accel, tf), (mut accel2, tf2)]) = iter.fetch_next() {
-        let d = Vec3::distance_squared(tf.translation, tf2.translation);
-        let repel_force = if d == 0. {
-            0.
-        } else {   
-            physics_config.repel_strength / d
-        };
-        let a_to_b = (tf2.translation - tf.translation).normalize_or_zero();
+    let dots: Vec<(Vec3, f32, f32)> = q.iter()
+        .map(|(_, tf, mass, charge, neighbors)| (tf.translation, physics_config.dot_mass(mass, neighbors), physics_config.dot_charge(charge, neighbors)))
+        .collect();
+    let wrap = boundary.wrap_bounds();
 
-        accel.0 -= a_to_b * repel_force;
-        accel2.0 += a_to_b * repel_force;
+    let mut forces = vec![Vec3::ZERO; dots.len()];
+    for (i, &(pos, _, charge)) in dots.iter().enumerate() {
+        for (j, &(pos2, _, charge2)) in dots.iter().enumerate().skip(i + 1) {
+            let offset = wrapped_offset(pos.xy(), pos2.xy(), wrap).extend(0.);
+            let d = offset.length_squared();
+            let repel_force = if d == 0. {
+                0.
+            } else {
+                physics_config.repel_strength * charge * charge2 / d
+            };
+            let a_to_b = offset.normalize_or_zero();
+
+            forces[i] -= a_to_b * repel_force;
+            forces[j] += a_to_b * repel_force;
+        }
+    }
+
+    for ((mut accel, ..), (force, (_, mass, _))) in q.iter_mut().zip(forces.iter().zip(dots.iter())) {
+        accel.0 += *force / *mass;
     }
 }
 
 pub fn apply_attraction_between_edges(
-    mut q: Query<(&mut Acceleration, &Transform, &Neighbors, Entity), With<Dot>>,
+    edges_q: Query<&Edge>,
+    mut dots_q: Query<MassiveDot, With<Dot>>,
     physics_config: Res<PhysicsConfig>,
+    boundary: Res<Boundary>,
 ) {
-    let mut pairs = Vec::new();
-    for (_, _, neighbors, eid) in q.iter() {
-        for neighbor_eid in neighbors.neighbors.iter().cloned() {
-            pairs.push((eid, neighbor_eid));
-        }
-    }
+    let wrap = boundary.wrap_bounds();
+    for edge in edges_q.iter() {
+        // the edge may outlive its dots for a frame while the graph is being rebuilt
+        let Ok([mut e1, mut e2]) = dots_q.get_many_mut([edge.source, edge.target]) else { continue };
 
-    for (eid, neighbor_eid) in pairs {
-        let [mut e1, mut e2] = q.get_many_mut([eid, neighbor_eid]).unwrap();
+        let resting_length = physics_config.edge_resting_length(edge);
+        let spring_coefficient = physics_config.edge_spring_coefficient(edge);
 
-        let d = f32::max(Vec3::distance(e1.1.translation, e2.1.translation) - physics_config.spring_resting_length, 0.);
-        let spring_force = physics_config.spring_coefficient * d;
+        let offset = wrapped_offset(e1.1.translation.xy(), e2.1.translation.xy(), wrap).extend(0.);
+        let d = f32::max(offset.length() - resting_length, 0.);
+        let spring_force = spring_coefficient * d;
 
-        let a_to_b = (e2.1.translation - e1.1.translation).normalize();
+        let a_to_b = offset.normalize_or_zero();
 
-        e1.0.0 += a_to_b * spring_force;
-        e2.0.0 -= a_to_b * spring_force;
+        e1.0.0 += a_to_b * spring_force / physics_config.dot_mass(e1.2, e1.3);
+        e2.0.0 -= a_to_b * spring_force / physics_config.dot_mass(e2.2, e2.3);
     }
 }
 
+/// Pushes Dots away from the walls of the boundary, when its walls repel
 pub fn apply_force_between_dots_and_walls(
+    mut q: Query<MassiveDot, With<Dot>>,
+    physics_config: Res<PhysicsConfig>,
+    boundary: Res<Boundary>,
+) {
+    if boundary.behaviour != WallBehaviour::SoftRepulsion {
+        return;
+    }
+
+    for (mut accel, tf, mass, neighbors) in q.iter_mut() {
+        let mass = physics_config.dot_mass(mass, neighbors);
+        let force = boundary.shape.wall_force(tf.translation.xy(), physics_config.wall_repel_strength);
+        accel.0 += (force / mass).extend(0.);
+    }
+}
+
+/// Pulls Dots towards the centre of the boundary and along the gravity vector. Like real gravity,
+/// these accelerate every Dot the same however heavy it is
+pub fn apply_gravity(
     mut q: Query<(&mut Acceleration, &Transform), With<Dot>>,
-    physics_config: Res<PhysicsConfig>
+    physics_config: Res<PhysicsConfig>,
+    boundary: Res<Boundary>,
 ) {
+    let center = boundary.shape.center();
     for (mut accel, tf) in q.iter_mut() {
-        let right_wall_force = physics_config.wall_repel_strength / f32::powi(f32::max(WIN_SIZE.0 - tf.translation.x, 0.0001), 2);
-        let left_wall_force = physics_config.wall_repel_strength / f32::powi(f32::max(tf.translation.x, 0.0001), 2);
-        let bottom_wall_force = physics_config.wall_repel_strength / f32::powi(f32::max(WIN_SIZE.1 - tf.translation.y, 0.0001), 2);
-        let top_wall_force = physics_config.wall_repel_strength / f32::powi(f32::max(tf.translation.y, 0.0001), 2);
+        let to_center = center - tf.translation.xy();
+        let pull = match physics_config.center_gravity_falloff {
+            GravityFalloff::Linear => to_center * physics_config.center_gravity,
+            GravityFalloff::Constant => to_center.normalize_or_zero() * physics_config.center_gravity,
+        };
+        accel.0 += (pull + physics_config.gravity).extend(0.);
+    }
+}
 
-        // apply forces for each wall
-        accel.0.x += left_wall_force - right_wall_force;
-        accel.0.y += top_wall_force - bottom_wall_force;
+/// Moves every connected component's centroid towards the centre without changing its shape, so
+/// disconnected pieces don't drift off into the walls
+pub fn apply_component_centering(
+    mut q: Query<(Entity, &mut Acceleration, &Transform, &Neighbors), With<Dot>>,
+    physics_config: Res<PhysicsConfig>,
+    boundary: Res<Boundary>,
+) {
+    if physics_config.component_centering == 0. {
+        return;
+    }
+
+    let adjacency = Adjacency::from_neighbors(q.iter().map(|(eid, _, _, neighbors)| (eid, neighbors)));
+    let components = connected_components(&adjacency);
+    let mut sums = vec![(Vec2::ZERO, 0.); components.iter().max().map_or(0, |c| c + 1)];
+    for (eid, &component) in adjacency.entities.iter().zip(components.iter()) {
+        let (_, _, tf, _) = q.get(*eid).unwrap();
+        sums[component].0 += tf.translation.xy();
+        sums[component].1 += 1.;
+    }
+
+    let center = boundary.shape.center();
+    for (eid, &component) in adjacency.entities.iter().zip(components.iter()) {
+        let (sum, count) = sums[component];
+        let (_, mut accel, ..) = q.get_mut(*eid).unwrap();
+        accel.0 += ((center - sum / count) * physics_config.component_centering).extend(0.);
+    }
+}
+
+/// Pulls every Dot towards the centroid of the Dots in its community, so communities cluster
+/// together
+pub fn apply_community_attraction(
+    mut q: Query<(&mut Acceleration, &Transform, &Mass, &Neighbors, &Community), With<Dot>>,
+    physics_config: Res<PhysicsConfig>,
+) {
+    if physics_config.community_attraction == 0. {
+        return;
+    }
+
+    let mut sums: HashMap<usize, (Vec3, f32)> = HashMap::new();
+    for (_, tf, .., community) in q.iter() {
+        let sum = sums.entry(community.0).or_insert((Vec3::ZERO, 0.));
+        sum.0 += tf.translation;
+        sum.1 += 1.;
+    }
+
+    for (mut accel, tf, mass, neighbors, community) in q.iter_mut() {
+        let (sum, count) = sums[&community.0];
+        accel.0 += (sum / count - tf.translation) * physics_config.community_attraction / physics_config.dot_mass(mass, neighbors);
     }
 }
 
@@ -146,3 +379,183 @@ pub fn accel_dampen(
         }
     }
 }
+
+/// Every pair of points closer than `distance`, found by bucketing them into a grid of cells that
+/// size, so only points in neighboring cells are compared. Pairs are in order of their first point.
+/// When wrapping, points are also close across the edges of the `wrap` rectangle
+pub fn close_pairs(points: &[Vec2], distance: f32, wrap: Option<Rect>) -> Vec<(usize, usize)> {
+    let cell_size = distance.max(f32::EPSILON);
+    // wrapped cells tile the rectangle exactly, so the last cell in each row neighbors the first.
+    // Fitting a whole number of them only makes them bigger, which still finds every pair
+    let wrapped_cells = wrap.map(|bounds| (bounds, (bounds.size() / cell_size).floor().max(Vec2::ONE)));
+    let cell_of = |p: Vec2| match wrapped_cells {
+        Some((bounds, cells)) => {
+            let cell = ((p - bounds.min).rem_euclid(bounds.size()) / bounds.size() * cells).floor().min(cells - 1.);
+            (cell.x as i32, cell.y as i32)
+        }
+        None => ((p.x / cell_size).floor() as i32, (p.y / cell_size).floor() as i32),
+    };
+    let mut grid: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+    for (i, &p) in points.iter().enumerate() {
+        grid.entry(cell_of(p)).or_default().push(i);
+    }
+
+    let mut pairs = Vec::new();
+    let mut neighbor_cells = Vec::with_capacity(9);
+    for (i, &p) in points.iter().enumerate() {
+        let (x, y) = cell_of(p);
+        neighbor_cells.clear();
+        for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (0, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+            neighbor_cells.push(match wrapped_cells {
+                Some((_, cells)) => ((x + dx).rem_euclid(cells.x as i32), (y + dy).rem_euclid(cells.y as i32)),
+                None => (x + dx, y + dy),
+            });
+        }
+        // with fewer than 3 cells across, wrapping reaches the same cell from both sides
+        neighbor_cells.sort_unstable();
+        neighbor_cells.dedup();
+
+        for neighbor_cell in neighbor_cells.iter() {
+            let Some(cell) = grid.get(neighbor_cell) else { continue };
+            pairs.extend(
+                cell.iter()
+                    .filter(|&&j| j > i && wrapped_offset(p, points[j], wrap).length_squared() < distance * distance)
+                    .map(|&j| (i, j)),
+            );
+        }
+    }
+    pairs
+}
+
+/// Pushes overlapping Dots apart until they just touch, and bounces them off each other by
+/// reflecting the part of their velocities that brings them together
+pub fn resolve_collisions(
+    mut q: Query<(&mut Transform, &mut Velocity, &Mass, &Neighbors), With<Dot>>,
+    physics_config: Res<PhysicsConfig>,
+    boundary: Res<Boundary>,
+) {
+    if !physics_config.collisions {
+        return;
+    }
+
+    let min_distance = 2. * physics_config.collision_radius;
+    let mut positions: Vec<Vec2> = q.iter().map(|(tf, ..)| tf.translation.xy()).collect();
+    let mut velocities: Vec<Vec2> = q.iter().map(|(_, vel, ..)| vel.0.xy()).collect();
+    let inverse_masses: Vec<f32> = q.iter().map(|(_, _, mass, neighbors)| 1. / physics_config.dot_mass(mass, neighbors)).collect();
+    let wrap = boundary.wrap_bounds();
+
+    for _ in 0..COLLISION_MAX_ITERATIONS {
+        let pairs = close_pairs(&positions, min_distance, wrap);
+        if pairs.is_empty() {
+            break;
+        }
+        for (i, j) in pairs {
+            let offset = wrapped_offset(positions[i], positions[j], wrap);
+            let distance = offset.length();
+            if distance >= min_distance {
+                continue;
+            }
+            // Dots exactly on top of each other have no direction between them, so pick one
+            let normal = if distance > 0. { offset / distance } else { Vec2::from_angle(i as f32) };
+
+            // the lighter Dot moves further
+            let inverse_mass = inverse_masses[i] + inverse_masses[j];
+            let push = normal * (min_distance + COLLISION_SLOP - distance) / inverse_mass;
+            positions[i] -= push * inverse_masses[i];
+            positions[j] += push * inverse_masses[j];
+
+            let approach = (velocities[j] - velocities[i]).dot(normal);
+            if approach < 0. {
+                let impulse = normal * approach * (1. + physics_config.restitution) / inverse_mass;
+                velocities[i] += impulse * inverse_masses[i];
+                velocities[j] -= impulse * inverse_masses[j];
+            }
+        }
+    }
+
+    for ((mut tf, mut vel, ..), (pos, v)) in q.iter_mut().zip(positions.into_iter().zip(velocities)) {
+        tf.translation = pos.extend(tf.translation.z);
+        vel.0 = v.extend(vel.0.z);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    /// Every pair closer than `distance`, by comparing each point with every other
+    fn brute_force_pairs(points: &[Vec2], distance: f32, wrap: Option<Rect>) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for i in 0..points.len() {
+            for j in i + 1..points.len() {
+                if wrapped_offset(points[i], points[j], wrap).length_squared() < distance * distance {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+
+    #[test]
+    fn close_pairs_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for distance in [1., 8., 50.] {
+            // spread across zero, so cells with negative coordinates are covered too
+            let points: Vec<Vec2> = (0..500).map(|_| Vec2::new(rng.gen_range(-200.0..200.0), rng.gen_range(-100.0..100.0))).collect();
+            let mut pairs = close_pairs(&points, distance, None);
+            pairs.sort();
+            assert_eq!(pairs, brute_force_pairs(&points, distance, None), "distance {}", distance);
+        }
+    }
+
+    #[test]
+    fn wrapped_close_pairs_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let bounds = Rect::new(-50., 0., 250., 100.);
+        // the largest distance leaves fewer than 3 cells across, so cells neighbor each other twice
+        for distance in [1., 8., 45.] {
+            // some just outside, as they are before being wrapped back in
+            let points: Vec<Vec2> = (0..500).map(|_| Vec2::new(rng.gen_range(-55.0..255.0), rng.gen_range(-5.0..105.0))).collect();
+            let mut pairs = close_pairs(&points, distance, Some(bounds));
+            pairs.sort();
+            assert_eq!(pairs, brute_force_pairs(&points, distance, Some(bounds)), "distance {}", distance);
+        }
+    }
+
+    #[test]
+    fn close_pairs_across_cell_edges() {
+        // the first point's neighbors lie in different cells just inside the distance, and the last
+        // is exactly the distance away so isn't close to it
+        let points = [Vec2::ZERO, Vec2::new(-9.9, 0.), Vec2::new(0., 9.9), Vec2::new(7., -7.), Vec2::new(10., 0.)];
+        let mut pairs = close_pairs(&points, 10., None);
+        pairs.sort();
+        assert_eq!(pairs, brute_force_pairs(&points, 10., None));
+        assert_eq!(pairs, vec![(0, 1), (0, 2), (0, 3), (3, 4)]);
+    }
+
+    #[test]
+    fn coincident_dots_are_separated() {
+        let mut world = World::new();
+        world.insert_resource(PhysicsConfig { collisions: true, ..default() });
+        world.insert_resource(Boundary::default());
+        for _ in 0..4 {
+            world.spawn((
+                Dot,
+                Transform::from_xyz(10., 20., 0.),
+                Velocity(Vec3::ZERO),
+                Mass::default(),
+                Neighbors { neighbors: Vec::new() },
+            ));
+        }
+
+        world.run_system_once(resolve_collisions);
+
+        let min_distance = 2. * COLLISION_RADIUS;
+        let positions: Vec<Vec2> = world.query_filtered::<&Transform, With<Dot>>().iter(&world).map(|tf| tf.translation.xy()).collect();
+        assert!(positions.iter().all(|p| p.is_finite()));
+        assert!(close_pairs(&positions, min_distance, None).is_empty(), "{:?} still overlap", positions);
+    }
+}
@@ -0,0 +1,86 @@
+use std::fs;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{boundary::Boundary, obstacles::Obstacles, physics::PhysicsConfig};
+
+pub const PRESET_PATH: &str = "preset.ron";
+
+/// The physics and everything Dots move around in, saved together so a scene can be set up again
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preset {
+    pub physics: PhysicsConfig,
+    pub boundary: Boundary,
+    pub obstacles: Obstacles,
+}
+
+impl Preset {
+    pub fn capture(world: &World) -> Self {
+        Preset {
+            physics: world.resource::<PhysicsConfig>().clone(),
+            boundary: world.resource::<Boundary>().clone(),
+            obstacles: world.resource::<Obstacles>().clone(),
+        }
+    }
+
+    pub fn apply(self, world: &mut World) {
+        *world.resource_mut::<PhysicsConfig>() = self.physics;
+        *world.resource_mut::<Boundary>() = self.boundary;
+        *world.resource_mut::<Obstacles>() = self.obstacles;
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        ron::from_str(&contents).map_err(|e| e.to_string())
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{boundary::{BoundaryShape, WallBehaviour}, obstacles::{ForceField, Obstacle}};
+
+    use super::*;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.insert_resource(PhysicsConfig::default());
+        world.insert_resource(Boundary::default());
+        world.insert_resource(Obstacles::default());
+        world
+    }
+
+    #[test]
+    fn presets_round_trip_through_a_file() {
+        let mut saved = world();
+        saved.resource_mut::<PhysicsConfig>().collisions = true;
+        saved.resource_mut::<PhysicsConfig>().gravity = Vec2::new(0., -2.);
+        *saved.resource_mut::<Boundary>() = Boundary {
+            shape: BoundaryShape::window_circle(),
+            behaviour: WallBehaviour::Bounce,
+            restitution: 0.3,
+        };
+        *saved.resource_mut::<Obstacles>() = Obstacles {
+            obstacles: vec![Obstacle::polygon(Vec2::new(100., 50.))],
+            fields: vec![ForceField::new(Vec2::ZERO)],
+        };
+
+        let path = std::env::temp_dir().join("graph-physics-preset-test.ron");
+        let path = path.to_str().unwrap();
+        Preset::capture(&saved).save(path).unwrap();
+        let loaded = Preset::load(path);
+        let _ = fs::remove_file(path);
+
+        let mut applied = world();
+        loaded.unwrap().apply(&mut applied);
+        assert_eq!(applied.resource::<PhysicsConfig>(), saved.resource::<PhysicsConfig>());
+        assert_eq!(applied.resource::<Boundary>(), saved.resource::<Boundary>());
+        assert_eq!(applied.resource::<Obstacles>(), saved.resource::<Obstacles>());
+    }
+}
@@ -0,0 +1,302 @@
+use std::{collections::VecDeque, f32::consts::TAU, fmt::Write as _, fs};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{graph::{all_pairs_shortest_paths, Adjacency, Dot, Neighbors}, style::DotStyle};
+
+/// How many samples are kept for the graph in the UI
+const QUALITY_HISTORY_LEN: usize = 600;
+/// How many frames apart samples are taken when measuring continuously
+const QUALITY_INTERVAL: u32 = 10;
+
+/// How readable the current layout is, by several measures
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct LayoutQuality {
+    /// Pairs of edges which cross, not counting edges that share a Dot
+    pub crossings: usize,
+    /// The variance of every edge's length, in pixels squared
+    pub edge_length_variance: f32,
+    /// The smallest angle between two edges at the same Dot, in degrees
+    pub min_angle: f32,
+    /// Pairs of Dots whose circles overlap
+    pub overlaps: usize,
+    /// How far distances between Dots are from their graph distances, once the layout is scaled to
+    /// fit them as well as it can. 0 is a perfect fit
+    pub stress: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum QualityMetric {
+    Crossings,
+    EdgeLengthVariance,
+    MinAngle,
+    Overlaps,
+    Stress,
+}
+
+impl QualityMetric {
+    pub const ALL: [QualityMetric; 5] = [
+        QualityMetric::Crossings,
+        QualityMetric::EdgeLengthVariance,
+        QualityMetric::MinAngle,
+        QualityMetric::Overlaps,
+        QualityMetric::Stress,
+    ];
+
+    pub fn value(&self, quality: &LayoutQuality) -> f32 {
+        match self {
+            QualityMetric::Crossings => quality.crossings as f32,
+            QualityMetric::EdgeLengthVariance => quality.edge_length_variance,
+            QualityMetric::MinAngle => quality.min_angle,
+            QualityMetric::Overlaps => quality.overlaps as f32,
+            QualityMetric::Stress => quality.stress,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            QualityMetric::Crossings => "Crossings",
+            QualityMetric::EdgeLengthVariance => "Edge Length Variance",
+            QualityMetric::MinAngle => "Min Angle",
+            QualityMetric::Overlaps => "Overlaps",
+            QualityMetric::Stress => "Stress",
+        }
+    }
+
+    /// The name as a CSV column
+    pub fn column(&self) -> &'static str {
+        match self {
+            QualityMetric::Crossings => "crossings",
+            QualityMetric::EdgeLengthVariance => "edge_length_variance",
+            QualityMetric::MinAngle => "min_angle",
+            QualityMetric::Overlaps => "overlaps",
+            QualityMetric::Stress => "stress",
+        }
+    }
+}
+
+/// Whether segments `a` and `b` cross at a point inside both of them
+fn segments_cross(a: (Vec2, Vec2), b: (Vec2, Vec2)) -> bool {
+    let side = |p: Vec2, q: Vec2, r: Vec2| (q - p).perp_dot(r - p);
+    let (d1, d2) = (side(b.0, b.1, a.0), side(b.0, b.1, a.1));
+    let (d3, d4) = (side(a.0, a.1, b.0), side(a.0, a.1, b.1));
+    d1 * d2 < 0. && d3 * d4 < 0.
+}
+
+impl LayoutQuality {
+    pub fn measure(adjacency: &Adjacency, positions: &[Vec2], radii: &[f32]) -> Self {
+        let n = adjacency.len();
+        let edges: Vec<(usize, usize)> = adjacency.adjacent.iter().enumerate()
+            .flat_map(|(i, a)| a.iter().filter(move |&&j| i < j).map(move |&j| (i, j)))
+            .collect();
+
+        let mut crossings = 0;
+        for (k, &(a, b)) in edges.iter().enumerate() {
+            for &(c, d) in edges[k + 1..].iter() {
+                if a == c || a == d || b == c || b == d {
+                    continue;
+                }
+                if segments_cross((positions[a], positions[b]), (positions[c], positions[d])) {
+                    crossings += 1;
+                }
+            }
+        }
+
+        let lengths: Vec<f32> = edges.iter().map(|&(a, b)| positions[a].distance(positions[b])).collect();
+        let mean = lengths.iter().sum::<f32>() / lengths.len().max(1) as f32;
+        let edge_length_variance = if lengths.is_empty() {
+            0.
+        } else {
+            lengths.iter().map(|l| (l - mean).powi(2)).sum::<f32>() / lengths.len() as f32
+        };
+
+        // the smallest gap between the directions of consecutive edges around each Dot
+        let mut min_angle = 360f32;
+        for (i, a) in adjacency.adjacent.iter().enumerate().filter(|(_, a)| a.len() > 1) {
+            let mut angles: Vec<f32> = a.iter()
+                .map(|&j| {
+                    let direction = positions[j] - positions[i];
+                    direction.y.atan2(direction.x).rem_euclid(TAU)
+                })
+                .collect();
+            angles.sort_by(f32::total_cmp);
+            let wrap = angles[0] + TAU - angles[angles.len() - 1];
+            let smallest = angles.windows(2).map(|w| w[1] - w[0]).fold(wrap, f32::min);
+            min_angle = min_angle.min(smallest.to_degrees());
+        }
+
+        let mut overlaps = 0;
+        for i in 0..n {
+            for j in i + 1..n {
+                if positions[i].distance(positions[j]) < radii[i] + radii[j] {
+                    overlaps += 1;
+                }
+            }
+        }
+
+        // weighted by 1/d², as in stress majorization, and scaled by whatever factor minimizes it
+        let graph_distances = all_pairs_shortest_paths(&adjacency.adjacent);
+        let pairs: Vec<(f32, f32)> = (0..n)
+            .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+            .map(|(i, j)| (graph_distances[i][j], positions[i].distance(positions[j])))
+            .filter(|(d, _)| d.is_finite() && *d > 0.)
+            .collect();
+        let numerator: f32 = pairs.iter().map(|(d, x)| x / d).sum();
+        let denominator: f32 = pairs.iter().map(|(d, x)| (x / d).powi(2)).sum();
+        let scale = if denominator > 0. { numerator / denominator } else { 0. };
+        let stress = if pairs.is_empty() {
+            0.
+        } else {
+            pairs.iter().map(|(d, x)| ((scale * x - d) / d).powi(2)).sum::<f32>() / pairs.len() as f32
+        };
+
+        LayoutQuality {
+            crossings,
+            edge_length_variance,
+            min_angle: if min_angle == 360. { 0. } else { min_angle },
+            overlaps,
+            stress,
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct LayoutQualityConfig {
+    /// Measure every `interval` frames rather than only when asked
+    pub continuous: bool,
+    pub interval: u32,
+    /// How many samples to keep
+    pub history_len: usize,
+    /// Set to measure on the next frame
+    pub requested: bool,
+    /// Which metric the UI graphs
+    pub graphed: QualityMetric,
+}
+
+impl Default for LayoutQualityConfig {
+    fn default() -> Self {
+        LayoutQualityConfig {
+            continuous: false,
+            interval: QUALITY_INTERVAL,
+            history_len: QUALITY_HISTORY_LEN,
+            requested: false,
+            graphed: QualityMetric::Crossings,
+        }
+    }
+}
+
+/// Every layout quality sample taken, oldest first, with the frame it was taken on
+#[derive(Resource, Debug, Default)]
+pub struct LayoutQualityLog {
+    pub samples: VecDeque<(u32, LayoutQuality)>,
+    frame: u32,
+}
+
+impl LayoutQualityLog {
+    pub fn latest(&self) -> Option<&LayoutQuality> {
+        self.samples.back().map(|(_, quality)| quality)
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("frame");
+        for metric in QualityMetric::ALL {
+            csv.push(',');
+            csv.push_str(metric.column());
+        }
+        csv.push('\n');
+        for (frame, quality) in self.samples.iter() {
+            let _ = write!(csv, "{}", frame);
+            for metric in QualityMetric::ALL {
+                let _ = write!(csv, ",{}", metric.value(quality));
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    pub fn write_csv(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_csv()).map_err(|e| e.to_string())
+    }
+}
+
+/// Measures the layout when asked to, or every few frames while measuring continuously
+pub fn measure_layout_quality(
+    dots_q: Query<(Entity, &Neighbors, &Transform, &DotStyle), With<Dot>>,
+    mut config: ResMut<LayoutQualityConfig>,
+    mut log: ResMut<LayoutQualityLog>,
+) {
+    let frame = log.frame;
+    log.frame += 1;
+    let due = config.continuous && frame.is_multiple_of(config.interval.max(1));
+    if !due && !config.requested {
+        return;
+    }
+    config.requested = false;
+
+    let adjacency = Adjacency::from_neighbors(dots_q.iter().map(|(eid, neighbors, ..)| (eid, neighbors)));
+    let (positions, radii): (Vec<Vec2>, Vec<f32>) = adjacency.entities.iter()
+        .map(|eid| dots_q.get(*eid).map_or((Vec2::ZERO, 0.), |(_, _, tf, style)| (tf.translation.xy(), style.radius)))
+        .unzip();
+
+    log.samples.push_back((frame, LayoutQuality::measure(&adjacency, &positions, &radii)));
+    while log.samples.len() > config.history_len {
+        log.samples.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An undirected `Adjacency` from a list of edges
+    fn graph(n: usize, edges: &[(usize, usize)]) -> Adjacency {
+        let mut adjacent = vec![Vec::new(); n];
+        for &(a, b) in edges {
+            adjacent[a].push(b);
+            adjacent[b].push(a);
+        }
+        Adjacency { entities: (0..n as u32).map(Entity::from_raw).collect(), adjacent }
+    }
+
+    #[test]
+    fn crossing_segments_are_counted_once() {
+        // the diagonals of a square cross, its sides only share corners
+        let positions = [Vec2::new(0., 0.), Vec2::new(10., 0.), Vec2::new(10., 10.), Vec2::new(0., 10.)];
+        let crossed = LayoutQuality::measure(&graph(4, &[(0, 2), (1, 3)]), &positions, &[1.; 4]);
+        assert_eq!(crossed.crossings, 1);
+        let square = LayoutQuality::measure(&graph(4, &[(0, 1), (1, 2), (2, 3), (3, 0)]), &positions, &[1.; 4]);
+        assert_eq!(square.crossings, 0);
+        assert_eq!(square.edge_length_variance, 0.);
+    }
+
+    #[test]
+    fn min_angle_is_the_narrowest_gap_between_edges() {
+        // edges from 0 going right, up and diagonally between them
+        let positions = [Vec2::ZERO, Vec2::new(10., 0.), Vec2::new(0., 10.), Vec2::new(10., 10.)];
+        let quality = LayoutQuality::measure(&graph(4, &[(0, 1), (0, 2), (0, 3)]), &positions, &[1.; 4]);
+        assert!((quality.min_angle - 45.).abs() < 1e-3);
+        // a lone edge has no angle to measure
+        let quality = LayoutQuality::measure(&graph(2, &[(0, 1)]), &positions[..2], &[1.; 2]);
+        assert_eq!(quality.min_angle, 0.);
+    }
+
+    #[test]
+    fn overlaps_count_pairs_closer_than_their_radii() {
+        let positions = [Vec2::ZERO, Vec2::new(5., 0.), Vec2::new(9., 0.), Vec2::new(100., 0.)];
+        let quality = LayoutQuality::measure(&graph(4, &[]), &positions, &[3.; 4]);
+        // 0 and 1 overlap, as do 1 and 2, but 0 and 2 are 9 apart
+        assert_eq!(quality.overlaps, 2);
+    }
+
+    #[test]
+    fn evenly_spaced_paths_have_no_stress() {
+        let positions: Vec<Vec2> = (0..5).map(|i| Vec2::new(i as f32 * 30., 0.)).collect();
+        let quality = LayoutQuality::measure(&graph(5, &[(0, 1), (1, 2), (2, 3), (3, 4)]), &positions, &[1.; 5]);
+        assert!(quality.stress < 1e-6);
+        // folding the path back on itself puts 0 and 2 together
+        let folded = [positions[0], positions[1], positions[0], positions[1], positions[0]];
+        let quality = LayoutQuality::measure(&graph(5, &[(0, 1), (1, 2), (2, 3), (3, 4)]), &folded, &[1.; 5]);
+        assert!(quality.stress > 0.1);
+    }
+}
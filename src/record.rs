@@ -0,0 +1,149 @@
+use std::{fs::File, io::BufWriter, path::{Path, PathBuf}, time::Duration};
+
+use bevy::{prelude::*, time::TimeUpdateStrategy};
+
+use crate::{export::ExportScene, FIXED_TIMESTEP_HZ, WIN_SIZE};
+
+pub const RECORD_DIRECTORY: &str = "recording";
+/// Frames are rasterised at this fraction of the window size to keep recordings small
+const RECORD_FRAME_SCALE: f32 = 0.5;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RecordFormat {
+    /// Numbered PNG frames
+    PngSequence,
+    /// Numbered PNG frames, combined into an animated PNG when the recording stops
+    Apng,
+}
+
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct RecordConfig {
+    pub format: RecordFormat,
+    pub directory: String,
+    pub frame_scale: f32,
+    /// Advance time by exactly one fixed timestep every frame, so runs are reproducible no
+    /// matter how fast frames are drawn
+    pub deterministic: bool,
+}
+
+impl Default for RecordConfig {
+    fn default() -> Self {
+        RecordConfig {
+            format: RecordFormat::PngSequence,
+            directory: RECORD_DIRECTORY.to_owned(),
+            frame_scale: RECORD_FRAME_SCALE,
+            deterministic: false,
+        }
+    }
+}
+
+/// Set `recording` to start or stop capturing a frame every fixed step
+#[derive(Resource, Default)]
+pub struct Recorder {
+    pub recording: bool,
+    /// How many frames have been written by the current recording
+    pub frames: u32,
+}
+
+pub fn frame_path(directory: &str, frame: u32) -> PathBuf {
+    Path::new(directory).join(format!("frame_{:05}.png", frame))
+}
+
+pub fn animation_path(directory: &str) -> PathBuf {
+    Path::new(directory).join("animation.png")
+}
+
+pub fn fixed_timestep() -> Duration {
+    Duration::from_secs_f64(1. / FIXED_TIMESTEP_HZ)
+}
+
+/// Combines the numbered frames of a recording into one animated PNG, playing at the fixed
+/// timestep rate
+pub fn write_apng(directory: &str, frames: u32) -> Result<PathBuf, String> {
+    let first = image::open(frame_path(directory, 0)).map_err(|e| e.to_string())?;
+    let path = animation_path(directory);
+    let file = File::create(&path).map_err(|e| e.to_string())?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), first.width(), first.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames, 0).map_err(|e| e.to_string())?;
+    encoder.set_frame_delay(1, FIXED_TIMESTEP_HZ as u16).map_err(|e| e.to_string())?;
+
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    for frame in 0..frames {
+        let image = image::open(frame_path(directory, frame)).map_err(|e| e.to_string())?.to_rgba8();
+        writer.write_image_data(&image).map_err(|e| e.to_string())?;
+    }
+    writer.finish().map_err(|e| e.to_string())?;
+
+    Ok(path)
+}
+
+/// Run condition for `record_frame`
+pub fn recording(recorder: Res<Recorder>) -> bool {
+    recorder.recording
+}
+
+/// Rasterises the current state to the next numbered frame. Runs after every fixed step, so the
+/// recording doesn't depend on the frame rate
+pub fn record_frame(world: &mut World) {
+    let scene = ExportScene::from_world(world);
+    let Some(config) = world.get_resource::<RecordConfig>() else { return };
+    let directory = config.directory.clone();
+    let size = Vec2::new(WIN_SIZE.0, WIN_SIZE.1) * config.frame_scale;
+
+    let mut recorder = world.resource_mut::<Recorder>();
+    if recorder.frames == 0 {
+        if let Err(e) = std::fs::create_dir_all(&directory) {
+            warn!("could not create {}: {}", directory, e);
+            recorder.recording = false;
+            return;
+        }
+    }
+
+    let path = frame_path(&directory, recorder.frames);
+    match scene.rasterize(size.x as u32, size.y as u32).save(&path) {
+        Ok(()) => recorder.frames += 1,
+        Err(e) => {
+            warn!("could not write {}: {}, stopping recording", path.display(), e);
+            recorder.recording = false;
+        }
+    }
+}
+
+/// Once a recording has stopped, writes its animation if one was asked for
+pub fn finish_recording(
+    mut recorder: ResMut<Recorder>,
+    record_config: Res<RecordConfig>,
+) {
+    if recorder.recording || recorder.frames == 0 {
+        return;
+    }
+
+    info!("recorded {} frames to {}", recorder.frames, record_config.directory);
+    if record_config.format == RecordFormat::Apng {
+        match write_apng(&record_config.directory, recorder.frames) {
+            Ok(path) => info!("wrote animation to {}", path.display()),
+            Err(e) => warn!("could not write animation: {}", e),
+        }
+    }
+    recorder.frames = 0;
+}
+
+/// Run condition for physics stepped on the fixed timestep rather than every frame
+pub fn deterministic(record_config: Res<RecordConfig>) -> bool {
+    record_config.deterministic
+}
+
+/// Switches between wall-clock time and stepping exactly one fixed timestep per frame
+pub fn apply_deterministic_mode(
+    record_config: Res<RecordConfig>,
+    mut time_update_strategy: ResMut<TimeUpdateStrategy>,
+) {
+    *time_update_strategy = if record_config.deterministic {
+        TimeUpdateStrategy::ManualDuration(fixed_timestep())
+    } else {
+        TimeUpdateStrategy::Automatic
+    };
+}
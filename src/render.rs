@@ -1,32 +1,266 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, render::{mesh::PrimitiveTopology, render_asset::RenderAssetUsages, view::NoFrustumCulling}, sprite::Mesh2dHandle};
 
-use crate::graph::{Dot, Neighbors, Partner};
+use crate::{boundary::{Boundary, BoundaryShape}, graph::{Dot, Edge, EdgeMode, GraphSpawnConfig, Partner}, obstacles::{ObstacleBehaviour, Obstacles}, pathfinding::{PathSelection, PathfindingConfig}, phases::Phases, style::{DotStyle, EdgeStyle, DOT_RADIUS}, ui::Inspector};
 
-const DOT_CIRCLE_RADIUS: f32 = 4.0;
+/// The opacity of the lightest edge when edge weights are shown
+const EDGE_WEIGHT_MIN_ALPHA: f32 = 0.15;
+/// The length of each side of an arrow head when edges are directed and drawn with meshes
+const ARROW_HEAD_LENGTH: f32 = 6.;
+/// Keeps the edge mesh behind the dots
+const EDGE_MESH_Z: f32 = -1.;
+/// The gap between a Dot and the ring drawn around it to highlight it
+const PATH_HIGHLIGHT_GAP: f32 = 3.;
+const PATH_START_COLOR: Color = Color::GREEN;
+const PATH_GOAL_COLOR: Color = Color::RED;
+const PATH_COLOR: Color = Color::YELLOW;
+const WAVEFRONT_COLOR: Color = Color::CYAN;
+/// Dots the wavefront has passed never fade out completely
+const WAVEFRONT_MIN_ALPHA: f32 = 0.2;
+const INSPECTED_COLOR: Color = Color::ORANGE;
+const BOUNDARY_COLOR: Color = Color::DARK_GRAY;
+const SOLID_OBSTACLE_COLOR: Color = Color::GRAY;
+const REPELLING_OBSTACLE_COLOR: Color = Color::PURPLE;
+const ATTRACTOR_COLOR: Color = Color::LIME_GREEN;
+const REPULSOR_COLOR: Color = Color::TOMATO;
+/// Field rings are faint so they don't hide the Dots inside them
+const FIELD_ALPHA: f32 = 0.3;
+/// Half the width of the cross drawn at the centre of a force field
+const FIELD_CROSS_SIZE: f32 = 5.;
 
-/// Render lines based on the values of every Dot's Neighbors component
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RenderMode {
+    /// Retained meshes: one per Dot and a single batched line mesh for every Edge
+    Meshes,
+    /// Immediate mode gizmos, redrawn every frame. Useful for debugging
+    Gizmos,
+}
+
+#[derive(Resource, Clone)]
+pub struct RenderConfig {
+    pub mode: RenderMode,
+    /// Draw heavier edges more opaque than lighter ones
+    pub show_edge_weights: bool,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        RenderConfig {
+            mode: RenderMode::Meshes,
+            show_edge_weights: false,
+        }
+    }
+}
+
+/// Run condition for the gizmo rendering systems
+pub fn using_gizmos(render_config: Res<RenderConfig>) -> bool {
+    render_config.mode == RenderMode::Gizmos
+}
+
+/// Run condition for the mesh rendering systems
+pub fn using_meshes(render_config: Res<RenderConfig>) -> bool {
+    render_config.mode == RenderMode::Meshes
+}
+
+/// The circle mesh shared by every Dot. Each Dot has its own material so it can be coloured
+/// individually, and is scaled to its radius
+#[derive(Resource)]
+pub struct DotMeshAssets {
+    mesh: Mesh2dHandle,
+}
+
+/// The entity holding the batched line mesh for every Edge
+#[derive(Component)]
+pub struct EdgeLines;
+
+pub fn edge_color(edge: &Edge, style: &EdgeStyle, max_weight: f32, render_config: &RenderConfig) -> Color {
+    if render_config.show_edge_weights && max_weight > 0. {
+        let t = edge.weight / max_weight;
+        style.color.with_a(style.color.a() * (EDGE_WEIGHT_MIN_ALPHA + (1. - EDGE_WEIGHT_MIN_ALPHA) * t))
+    } else {
+        style.color
+    }
+}
+
+/// Where a directed edge should end so that its head is visible outside of the target's circle
+fn arrow_end(pos: Vec2, target_pos: Vec2, target_radius: f32) -> Vec2 {
+    target_pos - (target_pos - pos).normalize_or_zero() * target_radius
+}
+
+/// The line segments that make up an edge: just the line when undirected, plus an arrow head
+/// when directed
+pub fn push_edge_lines(lines: &mut Vec<(Vec2, Vec2, Color)>, pos: Vec2, target_pos: Vec2, target_radius: f32, color: Color, edge_mode: &EdgeMode) {
+    match edge_mode {
+        EdgeMode::Undirected => lines.push((pos, target_pos, color)),
+        EdgeMode::Directed => {
+            let end = arrow_end(pos, target_pos, target_radius);
+            let back = (pos - end).normalize_or_zero() * ARROW_HEAD_LENGTH;
+            lines.push((pos, end, color));
+            lines.push((end, end + Vec2::from_angle(0.5).rotate(back), color));
+            lines.push((end, end + Vec2::from_angle(-0.5).rotate(back), color));
+        }
+    }
+}
+
+pub fn setup_mesh_rendering(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.insert_resource(DotMeshAssets {
+        mesh: Mesh2dHandle(meshes.add(Circle::new(DOT_RADIUS))),
+    });
+
+    let edge_mesh = Mesh::new(PrimitiveTopology::LineList, RenderAssetUsages::default())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0f32; 3]; 2])
+        .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, vec![[0f32; 4]; 2]);
+    commands.spawn((
+        EdgeLines,
+        ColorMesh2dBundle {
+            mesh: Mesh2dHandle(meshes.add(edge_mesh)),
+            material: materials.add(Color::WHITE),
+            transform: Transform::from_xyz(0., 0., EDGE_MESH_Z),
+            visibility: Visibility::Hidden,
+            ..Default::default()
+        },
+        // the mesh changes every frame, so its bounding box would go stale
+        NoFrustumCulling,
+    ));
+}
+
+/// Dots which haven't been given a mesh yet
+type UnmeshedDots = (With<Dot>, Without<Mesh2dHandle>);
+/// Dots whose mesh is out of date with their style
+type RestyledDots = (With<Dot>, Or<(Changed<DotStyle>, Added<Handle<ColorMaterial>>)>);
+
+/// Gives newly spawned Dots the components needed to be drawn as a mesh, without touching their
+/// `Transform`
+pub fn attach_dot_meshes(
+    mut commands: Commands,
+    q: Query<(Entity, &DotStyle), UnmeshedDots>,
+    dot_mesh_assets: Res<DotMeshAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for (e, style) in q.iter() {
+        commands.entity(e).insert((
+            dot_mesh_assets.mesh.clone(),
+            materials.add(style.color),
+            GlobalTransform::default(),
+            VisibilityBundle {
+                visibility: Visibility::Hidden,
+                ..Default::default()
+            },
+        ));
+    }
+}
+
+/// Keeps each Dot's material and scale in sync with its `DotStyle`
+pub fn update_dot_mesh_styles(
+    mut q: Query<(&DotStyle, &Handle<ColorMaterial>, &mut Transform), RestyledDots>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for (style, material, mut transform) in q.iter_mut() {
+        if let Some(material) = materials.get_mut(material) {
+            material.color = style.color;
+        }
+        transform.scale = Vec3::splat(style.radius / DOT_RADIUS);
+    }
+}
+
+/// Shows the retained meshes only for the phases that draw them, and only in mesh mode
+pub fn update_mesh_visibility(
+    phase: Res<State<Phases>>,
+    render_config: Res<RenderConfig>,
+    mut dots_q: Query<&mut Visibility, With<Dot>>,
+    mut edge_lines_q: Query<&mut Visibility, (With<EdgeLines>, Without<Dot>)>,
+) {
+    let meshes = render_config.mode == RenderMode::Meshes;
+    let show_dots = meshes && matches!(phase.get(), Phases::JustDots | Phases::Graph);
+    let show_edges = meshes && *phase.get() == Phases::Graph;
+
+    let visibility = |show: bool| if show { Visibility::Inherited } else { Visibility::Hidden };
+    for mut v in dots_q.iter_mut() {
+        v.set_if_neq(visibility(show_dots));
+    }
+    for mut v in edge_lines_q.iter_mut() {
+        v.set_if_neq(visibility(show_edges));
+    }
+}
+
+/// Rebuilds the batched line mesh from every Edge
+pub fn update_edge_mesh(
+    edges_q: Query<(&Edge, &EdgeStyle)>,
+    dots_q: Query<(&Transform, &DotStyle), With<Dot>>,
+    edge_lines_q: Query<&Mesh2dHandle, With<EdgeLines>>,
+    graph_spawn_config: Res<GraphSpawnConfig>,
+    render_config: Res<RenderConfig>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let Ok(Mesh2dHandle(handle)) = edge_lines_q.get_single() else { return };
+    let Some(mesh) = meshes.get_mut(handle) else { return };
+
+    let max_weight = edges_q.iter().map(|(edge, _)| edge.weight).fold(0., f32::max);
+
+    let mut lines: Vec<(Vec2, Vec2, Color)> = Vec::new();
+
+    for (edge, style) in edges_q.iter() {
+        let Ok([(source, _), (target, target_style)]) = dots_q.get_many([edge.source, edge.target]) else { continue };
+        let (pos, target_pos) = (source.translation.xy(), target.translation.xy());
+        let color = edge_color(edge, style, max_weight, &render_config);
+
+        push_edge_lines(&mut lines, pos, target_pos, target_style.radius, color, &graph_spawn_config.edge_mode);
+    }
+
+    // an empty vertex buffer can't be drawn, so fall back to an invisible line
+    if lines.is_empty() {
+        lines.push((Vec2::ZERO, Vec2::ZERO, Color::NONE));
+    }
+
+    let positions: Vec<[f32; 3]> = lines.iter()
+        .flat_map(|(a, b, _)| [[a.x, a.y, 0.], [b.x, b.y, 0.]])
+        .collect();
+    let colors: Vec<[f32; 4]> = lines.iter()
+        .flat_map(|(_, _, color)| [color.as_linear_rgba_f32(); 2])
+        .collect();
+
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+}
+
+/// Render a line for every Edge, with an arrow head when edges are directed
 pub fn render_graph_edges(
-    nodes_query: Query<(&Neighbors, &Transform), With<Dot>>,
+    edges_q: Query<(&Edge, &EdgeStyle)>,
+    dots_q: Query<(&Transform, &DotStyle), With<Dot>>,
+    graph_spawn_config: Res<GraphSpawnConfig>,
+    render_config: Res<RenderConfig>,
     mut gizmos: Gizmos
 ) {
-    for (neighbors, transform) in nodes_query.iter() {
-        let pos = transform.translation;
-        for neighbor_eid in neighbors.neighbors.iter().cloned() {
-            let neighbor_pos = nodes_query.get(neighbor_eid).unwrap().1.translation;
+    let max_weight = edges_q.iter().map(|(edge, _)| edge.weight).fold(0., f32::max);
 
-            // render the line
-            gizmos.line_2d(pos.xy(), neighbor_pos.xy(), Color::WHITE);
+    for (edge, style) in edges_q.iter() {
+        let Ok([(source, _), (target, target_style)]) = dots_q.get_many([edge.source, edge.target]) else { continue };
+        let (pos, target_pos) = (source.translation.xy(), target.translation.xy());
+
+        let color = edge_color(edge, style, max_weight, &render_config);
+
+        // render the line
+        match graph_spawn_config.edge_mode {
+            EdgeMode::Undirected => {
+                gizmos.line_2d(pos, target_pos, color);
+            }
+            EdgeMode::Directed => {
+                gizmos.arrow_2d(pos, arrow_end(pos, target_pos, target_style.radius), color);
+            }
         }
     }
 }
 
 /// Render a circle for every Dot
 pub fn render_dots(
-    q: Query<&Transform, With<Dot>>,
+    q: Query<(&Transform, &DotStyle), With<Dot>>,
     mut gizmos: Gizmos
 ) {
-    for transform in q.iter() {
-        gizmos.circle_2d(transform.translation.xy(), DOT_CIRCLE_RADIUS, Color::WHITE).segments(8);
+    for (transform, style) in q.iter() {
+        gizmos.circle_2d(transform.translation.xy(), style.radius, style.color).segments(8);
     }
 }
 
@@ -45,3 +279,92 @@ pub fn render_partners(
         }
     }
 }
+
+/// Rings the Dot selected in the inspector
+pub fn render_inspected_dot(
+    dots_q: Query<(&Transform, &DotStyle), With<Dot>>,
+    inspector: Res<Inspector>,
+    mut gizmos: Gizmos,
+) {
+    if let Some((tf, style)) = inspector.dot.and_then(|eid| dots_q.get(eid).ok()) {
+        gizmos.circle_2d(tf.translation.xy(), style.radius + PATH_HIGHLIGHT_GAP, INSPECTED_COLOR);
+    }
+}
+
+fn draw_shape(gizmos: &mut Gizmos, shape: &BoundaryShape, color: Color) {
+    match shape {
+        BoundaryShape::Rectangle { min, max } => {
+            let rect = Rect::from_corners(*min, *max);
+            gizmos.rect_2d(rect.center(), 0., rect.size(), color);
+        }
+        BoundaryShape::Circle { center, radius } => {
+            gizmos.circle_2d(*center, *radius, color);
+        }
+        BoundaryShape::Polygon { vertices } => {
+            gizmos.linestrip_2d(vertices.iter().chain(vertices.first()).copied(), color);
+        }
+        BoundaryShape::None => {}
+    }
+}
+
+pub fn render_boundary(boundary: Res<Boundary>, mut gizmos: Gizmos) {
+    draw_shape(&mut gizmos, &boundary.shape, BOUNDARY_COLOR);
+}
+
+/// Outlines every obstacle, and rings every force field with a cross at its centre
+pub fn render_obstacles(obstacles: Res<Obstacles>, mut gizmos: Gizmos) {
+    for obstacle in obstacles.obstacles.iter() {
+        let color = match obstacle.behaviour {
+            ObstacleBehaviour::Collide => SOLID_OBSTACLE_COLOR,
+            ObstacleBehaviour::Repel => REPELLING_OBSTACLE_COLOR,
+        };
+        draw_shape(&mut gizmos, &obstacle.shape, color);
+    }
+    for field in obstacles.fields.iter() {
+        let color = if field.strength >= 0. { ATTRACTOR_COLOR } else { REPULSOR_COLOR };
+        gizmos.circle_2d(field.center, field.radius, color.with_a(FIELD_ALPHA));
+        gizmos.line_2d(field.center - Vec2::X * FIELD_CROSS_SIZE, field.center + Vec2::X * FIELD_CROSS_SIZE, color);
+        gizmos.line_2d(field.center - Vec2::Y * FIELD_CROSS_SIZE, field.center + Vec2::Y * FIELD_CROSS_SIZE, color);
+    }
+}
+
+/// Highlights the chosen path's ends, sweeps a wavefront out through the Dots in the order the
+/// search reached them, and then draws the path itself
+pub fn render_path(
+    dots_q: Query<(&Transform, &DotStyle), With<Dot>>,
+    selection: Res<PathSelection>,
+    config: Res<PathfindingConfig>,
+    time: Res<Time>,
+    mut gizmos: Gizmos,
+) {
+    if !config.enabled {
+        return;
+    }
+
+    let dot = |eid: Entity| dots_q.get(eid).ok().map(|(tf, style)| (tf.translation.xy(), style.radius));
+    for (end, color) in [(selection.start, PATH_START_COLOR), (selection.goal, PATH_GOAL_COLOR)] {
+        if let Some((pos, radius)) = end.and_then(dot) {
+            gizmos.circle_2d(pos, radius + PATH_HIGHLIGHT_GAP, color);
+        }
+    }
+
+    // how far through the expansion the wavefront has got
+    let steps = selection.expansion.len() as f32;
+    let progress = (time.elapsed_seconds() - selection.searched_at) / config.wavefront_seconds.max(f32::EPSILON) * steps;
+    for (step, layer) in selection.expansion.iter().enumerate().take(progress.ceil() as usize) {
+        // the front is brightest, fading behind it
+        let age = (progress - step as f32).max(0.);
+        let alpha = (1. - age / steps.max(1.)).max(WAVEFRONT_MIN_ALPHA);
+        for (pos, radius) in layer.iter().filter_map(|eid| dot(*eid)) {
+            gizmos.circle_2d(pos, radius + PATH_HIGHLIGHT_GAP / 2., WAVEFRONT_COLOR.with_a(alpha));
+        }
+    }
+
+    if progress >= steps {
+        for pair in selection.path.windows(2) {
+            if let (Some((a, _)), Some((b, _))) = (dot(pair[0]), dot(pair[1])) {
+                gizmos.line_2d(a, b, PATH_COLOR);
+            }
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use std::fs;
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{boundary::Boundary, community::CommunityConfig, graph::{Dot, GraphSpawnConfig}, layout::StressLayoutConfig, obstacles::Obstacles, phases::{DotSpawnConfig, Phases, SpawnMethod}, physics::PhysicsConfig, record::RecordConfig, MousePosition, Randomness, SEED};
+
+pub const REPLAY_PATH: &str = "replay.ron";
+
+/// Everything that can be tweaked from the UI and changes how the simulation behaves
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimulationConfig {
+    pub physics: PhysicsConfig,
+    pub spawn_method: SpawnMethod,
+    pub dot_spawn: DotSpawnConfig,
+    pub graph_spawn: GraphSpawnConfig,
+    pub stress_layout: StressLayoutConfig,
+    /// Label propagation is random, and communities pull on the Dots
+    #[serde(default)]
+    pub community: CommunityConfig,
+    #[serde(default)]
+    pub boundary: Boundary,
+    #[serde(default)]
+    pub obstacles: Obstacles,
+}
+
+impl SimulationConfig {
+    pub fn from_world(world: &World) -> Self {
+        SimulationConfig {
+            physics: world.resource::<PhysicsConfig>().clone(),
+            spawn_method: world.resource::<SpawnMethod>().clone(),
+            dot_spawn: world.resource::<DotSpawnConfig>().clone(),
+            graph_spawn: world.resource::<GraphSpawnConfig>().clone(),
+            stress_layout: world.resource::<StressLayoutConfig>().clone(),
+            community: world.resource::<CommunityConfig>().clone(),
+            boundary: world.resource::<Boundary>().clone(),
+            obstacles: world.resource::<Obstacles>().clone(),
+        }
+    }
+
+    pub fn apply(self, world: &mut World) {
+        *world.resource_mut::<PhysicsConfig>() = self.physics;
+        *world.resource_mut::<SpawnMethod>() = self.spawn_method;
+        *world.resource_mut::<DotSpawnConfig>() = self.dot_spawn;
+        *world.resource_mut::<GraphSpawnConfig>() = self.graph_spawn;
+        *world.resource_mut::<StressLayoutConfig>() = self.stress_layout;
+        *world.resource_mut::<CommunityConfig>() = self.community;
+        *world.resource_mut::<Boundary>() = self.boundary;
+        *world.resource_mut::<Obstacles>() = self.obstacles;
+    }
+}
+
+/// What happened during one frame of a recording
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplayFrame {
+    pub mouse: Option<Vec2>,
+    /// The phase, if it changed this frame
+    pub phase: Option<Phases>,
+    /// The configuration, if it was changed this frame. It takes effect from the next frame
+    pub config: Option<SimulationConfig>,
+    /// Every Dot's position at the end of the frame, in query order, which is the same every
+    /// time a run is replayed
+    pub positions: Vec<Vec2>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReplayFile {
+    pub seed: u64,
+    pub config: SimulationConfig,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl ReplayFile {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        ron::from_str(&contents).map_err(|e| e.to_string())
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        // these get large, so don't pretty print them
+        let contents = ron::ser::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReplayMode {
+    Idle,
+    Recording,
+    Playing,
+}
+
+#[derive(Resource)]
+pub struct Replay {
+    pub mode: ReplayMode,
+    /// Set to change `mode` at the start of the next frame
+    pub requested: Option<ReplayMode>,
+    pub path: String,
+    /// The run being recorded or played, or the last one
+    pub timeline: Option<ReplayFile>,
+    /// The next frame to record or play
+    pub frame: usize,
+    /// The first frame where playback didn't match the recording
+    pub diverged_at: Option<usize>,
+    /// Set to pause the simulation and show the positions from a recorded frame
+    pub scrub: Option<usize>,
+    /// The positions to go back to when scrubbing stops
+    live_positions: Option<Vec<Vec2>>,
+    last_phase: Option<Phases>,
+    last_config: Option<SimulationConfig>,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Replay {
+            mode: ReplayMode::Idle,
+            requested: None,
+            path: REPLAY_PATH.to_owned(),
+            timeline: None,
+            frame: 0,
+            diverged_at: None,
+            scrub: None,
+            live_positions: None,
+            last_phase: None,
+            last_config: None,
+        }
+    }
+}
+
+fn dot_positions(world: &mut World) -> Vec<Vec2> {
+    world.query_filtered::<&Transform, With<Dot>>()
+        .iter(world)
+        .map(|tf| tf.translation.xy())
+        .collect()
+}
+
+fn set_dot_positions(world: &mut World, positions: &[Vec2]) {
+    for (mut tf, pos) in world.query_filtered::<&mut Transform, With<Dot>>().iter_mut(world).zip(positions) {
+        tf.translation = pos.extend(tf.translation.z);
+    }
+}
+
+/// Restarts the simulation from the spawn phase with a fresh random number generator
+fn restart(world: &mut World, seed: u64) {
+    world.insert_resource(Randomness(StdRng::seed_from_u64(seed)));
+    world.resource_mut::<NextState<Phases>>().set(Phases::Init);
+}
+
+/// Starts and stops recording and playback. Runs at the start of a frame, once its time step is
+/// known, so that recording and playback start on the same step.
+pub fn begin_replay(world: &mut World) {
+    let Some(requested) = world.resource::<Replay>().requested else { return };
+
+    // replays are only exact if every frame has the same time step, which only takes effect
+    // from the next frame
+    if requested != ReplayMode::Idle && !world.resource::<RecordConfig>().deterministic {
+        world.resource_mut::<RecordConfig>().deterministic = true;
+        return;
+    }
+
+    let mut replay = world.resource_mut::<Replay>();
+    replay.requested = None;
+    let previous = replay.mode;
+    let path = replay.path.clone();
+
+    match requested {
+        ReplayMode::Idle => {
+            replay.mode = ReplayMode::Idle;
+            if previous == ReplayMode::Recording {
+                if let Some(timeline) = &replay.timeline {
+                    match timeline.save(&path) {
+                        Ok(()) => info!("saved {} replay frames to {}", timeline.frames.len(), path),
+                        Err(e) => warn!("could not save replay to {}: {}", path, e),
+                    }
+                }
+            }
+        }
+        ReplayMode::Recording => {
+            let config = SimulationConfig::from_world(world);
+            let mut replay = world.resource_mut::<Replay>();
+            replay.timeline = Some(ReplayFile { seed: SEED, config: config.clone(), frames: Vec::new() });
+            replay.mode = ReplayMode::Recording;
+            replay.frame = 0;
+            replay.last_phase = None;
+            replay.last_config = Some(config);
+            restart(world, SEED);
+        }
+        ReplayMode::Playing => {
+            let timeline = match ReplayFile::load(&path) {
+                Ok(timeline) => timeline,
+                Err(e) => {
+                    warn!("could not load replay from {}: {}", path, e);
+                    return;
+                }
+            };
+            let (seed, config) = (timeline.seed, timeline.config.clone());
+            replay.timeline = Some(timeline);
+            replay.mode = ReplayMode::Playing;
+            replay.frame = 0;
+            replay.diverged_at = None;
+            config.apply(world);
+            restart(world, seed);
+        }
+    }
+}
+
+/// Feeds the recorded phase changes and mouse position back in at the start of each frame
+pub fn play_replay_inputs(world: &mut World) {
+    let replay = world.resource::<Replay>();
+    if replay.mode != ReplayMode::Playing {
+        return;
+    }
+    let Some(frame) = replay.timeline.as_ref().and_then(|timeline| timeline.frames.get(replay.frame)) else {
+        let frames = replay.frame;
+        world.resource_mut::<Replay>().mode = ReplayMode::Idle;
+        info!("finished replaying {} frames", frames);
+        return;
+    };
+    let (phase, mouse) = (frame.phase, frame.mouse);
+
+    if let Some(phase) = phase {
+        world.resource_mut::<NextState<Phases>>().set(phase);
+    }
+    if let (Some(mouse), Some(mut mouse_position)) = (mouse, world.get_resource_mut::<MousePosition>()) {
+        mouse_position.0 = mouse;
+    }
+}
+
+/// At the end of each frame, either records what happened or, when playing, applies config changes
+/// and checks that the simulation still matches the recording
+pub fn end_replay_frame(world: &mut World) {
+    let mode = world.resource::<Replay>().mode;
+    if mode == ReplayMode::Idle {
+        return;
+    }
+
+    let positions = dot_positions(world);
+    let phase = *world.resource::<State<Phases>>().get();
+    let config = SimulationConfig::from_world(world);
+    let mouse = world.get_resource::<MousePosition>().map(|mouse| mouse.0);
+
+    let mut replay = world.resource_mut::<Replay>();
+    let index = replay.frame;
+    replay.frame += 1;
+
+    match mode {
+        ReplayMode::Recording => {
+            let frame = ReplayFrame {
+                mouse,
+                phase: (replay.last_phase != Some(phase)).then_some(phase),
+                config: (replay.last_config.as_ref() != Some(&config)).then(|| config.clone()),
+                positions,
+            };
+            replay.last_phase = Some(phase);
+            replay.last_config = Some(config);
+            if let Some(timeline) = replay.timeline.as_mut() {
+                timeline.frames.push(frame);
+            }
+        }
+        ReplayMode::Playing => {
+            let Some(frame) = replay.timeline.as_ref().and_then(|timeline| timeline.frames.get(index)) else { return };
+            let (matches, config) = (frame.positions == positions, frame.config.clone());
+            if replay.diverged_at.is_none() && !matches {
+                warn!("replay diverged from the recording at frame {}", index);
+                replay.diverged_at = Some(index);
+            }
+            if let Some(config) = config {
+                config.apply(world);
+            }
+        }
+        ReplayMode::Idle => {}
+    }
+}
+
+/// Run condition for systems that read live input, which would otherwise overwrite the recorded
+/// input being played back
+pub fn replaying(replay: Res<Replay>) -> bool {
+    replay.mode == ReplayMode::Playing
+}
+
+/// Run condition for systems that move Dots even while time is paused, which would otherwise move
+/// them away from the scrubbed positions
+pub fn not_scrubbing(replay: Res<Replay>) -> bool {
+    replay.live_positions.is_none()
+}
+
+/// While scrubbing, pauses the simulation and shows the recorded positions of the chosen frame.
+/// Once scrubbing stops, the positions from before it started are put back and the simulation
+/// carries on. Only possible while not recording or playing.
+pub fn scrub_replay(world: &mut World) {
+    let replay = world.resource::<Replay>();
+    let scrubbed = replay.scrub
+        .filter(|_| replay.mode == ReplayMode::Idle)
+        .and_then(|frame| replay.timeline.as_ref()?.frames.get(frame))
+        .map(|frame| frame.positions.clone());
+    let scrubbing = replay.live_positions.is_some();
+
+    match scrubbed {
+        Some(positions) => {
+            if !scrubbing {
+                let live = dot_positions(world);
+                world.resource_mut::<Replay>().live_positions = Some(live);
+                world.resource_mut::<Time<Virtual>>().pause();
+            }
+            set_dot_positions(world, &positions);
+        }
+        None if scrubbing => {
+            if let Some(live) = world.resource_mut::<Replay>().live_positions.take() {
+                set_dot_positions(world, &live);
+            }
+            world.resource_mut::<Time<Virtual>>().unpause();
+        }
+        None => {}
+    }
+}
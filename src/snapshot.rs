@@ -0,0 +1,209 @@
+use std::{collections::BTreeMap, fs};
+
+use bevy::{ecs::system::RunSystemOnce, prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{graph::{clear_edges, Dot, Edge, Neighbors, Partner}, phases::{clear_dots, DotAttributes, Phases}, physics::{Acceleration, Charge, Mass, Velocity, DOT_CHARGE, DOT_MASS}, style::{DotStyle, EdgeStyle}};
+
+pub const SNAPSHOT_PATH: &str = "snapshot.ron";
+
+/// Paths to save the world to or restore it from, filled in by the UI and handled by
+/// `snapshot_requested`
+#[derive(Resource, Default)]
+pub struct SnapshotRequests {
+    pub save: Option<String>,
+    pub load: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DotSnapshot {
+    /// The entity this Dot had when it was saved, which `Neighbors`, `Partner` and edges refer to
+    pub entity: Entity,
+    pub transform: Transform,
+    pub velocity: Vec3,
+    pub acceleration: Vec3,
+    pub neighbors: Vec<Entity>,
+    pub partner: Option<Entity>,
+    #[serde(default = "default_mass")]
+    pub mass: f32,
+    #[serde(default = "default_charge")]
+    pub charge: f32,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, f32>,
+}
+
+fn default_mass() -> f32 {
+    DOT_MASS
+}
+
+fn default_charge() -> f32 {
+    DOT_CHARGE
+}
+
+/// Every Dot and Edge, and the phase they were in
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorldSnapshot {
+    pub phase: Phases,
+    pub dots: Vec<DotSnapshot>,
+    pub edges: Vec<Edge>,
+}
+
+impl WorldSnapshot {
+    pub fn capture(world: &mut World) -> Self {
+        let phase = *world.resource::<State<Phases>>().get();
+        let dots = world.query_filtered::<(Entity, &Transform, &Velocity, &Acceleration, &Neighbors, &Partner, &Mass, &Charge, Option<&DotAttributes>), With<Dot>>()
+            .iter(world)
+            .map(|(entity, transform, velocity, acceleration, neighbors, partner, mass, charge, attributes)| DotSnapshot {
+                entity,
+                transform: *transform,
+                velocity: velocity.0,
+                acceleration: acceleration.0,
+                neighbors: neighbors.neighbors.clone(),
+                partner: partner.partner,
+                mass: mass.0,
+                charge: charge.0,
+                attributes: attributes.map(|a| a.0.clone()).unwrap_or_default(),
+            })
+            .collect();
+        let edges = world.query::<&Edge>().iter(world).cloned().collect();
+
+        WorldSnapshot { phase, dots, edges }
+    }
+
+    /// Replaces every Dot and Edge with the ones in the snapshot. They get new entities, so every
+    /// reference between them is mapped over to the new ones. The phase is set directly, without
+    /// running its transition, so that nothing is recomputed.
+    pub fn restore(self, world: &mut World) {
+        world.run_system_once(clear_dots);
+        world.run_system_once(clear_edges);
+
+        let entity_map: HashMap<Entity, Entity> = self.dots.iter()
+            .map(|dot| (dot.entity, world.spawn_empty().id()))
+            .collect();
+        let map = |entity: &Entity| entity_map.get(entity).cloned();
+
+        for dot in self.dots.iter() {
+            world.entity_mut(entity_map[&dot.entity]).insert((
+                Dot,
+                dot.transform,
+                Neighbors { neighbors: dot.neighbors.iter().filter_map(map).collect() },
+                Partner { partner: dot.partner.as_ref().and_then(map) },
+                Velocity(dot.velocity),
+                Acceleration(dot.acceleration),
+                Mass(dot.mass),
+                Charge(dot.charge),
+                DotStyle::default(),
+                DotAttributes(dot.attributes.clone()),
+            ));
+        }
+
+        for edge in self.edges {
+            let (Some(source), Some(target)) = (map(&edge.source), map(&edge.target)) else { continue };
+            world.spawn((Edge { source, target, ..edge }, EdgeStyle::default()));
+        }
+
+        world.insert_resource(State::new(self.phase));
+        world.resource_mut::<NextState<Phases>>().0 = None;
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        ron::from_str(&contents).map_err(|e| e.to_string())
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| e.to_string())
+    }
+}
+
+/// Handles any snapshot saves or loads requested from the UI
+pub fn snapshot_requested(world: &mut World) {
+    let Some(mut requests) = world.get_resource_mut::<SnapshotRequests>() else { return };
+    let (save, load) = (requests.save.take(), requests.load.take());
+
+    if let Some(path) = save {
+        match WorldSnapshot::capture(world).save(&path) {
+            Ok(()) => info!("saved snapshot to {}", path),
+            Err(e) => warn!("could not save snapshot to {}: {}", path, e),
+        }
+    }
+    if let Some(path) = load {
+        match WorldSnapshot::load(&path) {
+            Ok(snapshot) => {
+                snapshot.restore(world);
+                info!("restored snapshot from {}", path);
+            }
+            Err(e) => warn!("could not load snapshot from {}: {}", path, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn_dot(world: &mut World, x: f32) -> Entity {
+        world.spawn((
+            Dot,
+            Transform::from_xyz(x, 0., 0.),
+            Neighbors { neighbors: Vec::new() },
+            Partner { partner: None },
+            Velocity(Vec3::ZERO),
+            Acceleration(Vec3::ZERO),
+            Mass(DOT_MASS),
+            Charge(DOT_CHARGE),
+        )).id()
+    }
+
+    fn edge(source: Entity, target: Entity) -> Edge {
+        Edge { source, target, weight: 1., rest_length: None, stiffness: None, color: None, label: None }
+    }
+
+    /// Each Dot's x position, which stands in for its identity across a restore
+    fn x_of(world: &World, entity: Entity) -> f32 {
+        world.get::<Transform>(entity).expect("not a Dot").translation.x
+    }
+
+    #[test]
+    fn restored_references_point_at_the_new_dots() {
+        let mut world = World::new();
+        world.insert_resource(State::new(Phases::Graph));
+        world.init_resource::<NextState<Phases>>();
+
+        // a path 0 - 1 - 2, with 0 and 1 partnered
+        let old: Vec<Entity> = (0..3).map(|i| spawn_dot(&mut world, i as f32)).collect();
+        world.get_mut::<Neighbors>(old[0]).unwrap().neighbors = vec![old[1]];
+        world.get_mut::<Neighbors>(old[1]).unwrap().neighbors = vec![old[0], old[2]];
+        world.get_mut::<Neighbors>(old[2]).unwrap().neighbors = vec![old[1]];
+        world.get_mut::<Partner>(old[0]).unwrap().partner = Some(old[1]);
+        world.get_mut::<Partner>(old[1]).unwrap().partner = Some(old[0]);
+        world.spawn(edge(old[0], old[1]));
+        world.spawn(edge(old[1], old[2]));
+
+        WorldSnapshot::capture(&mut world).restore(&mut world);
+
+        let dots: Vec<(Entity, Vec<Entity>, Option<Entity>)> = world.query_filtered::<(Entity, &Neighbors, &Partner), With<Dot>>()
+            .iter(&world)
+            .map(|(entity, neighbors, partner)| (entity, neighbors.neighbors.clone(), partner.partner))
+            .collect();
+        assert_eq!(dots.len(), 3);
+        for (entity, neighbors, partner) in dots.iter() {
+            assert!(!old.contains(entity));
+            let neighbor_xs: Vec<f32> = neighbors.iter().map(|n| x_of(&world, *n)).collect();
+            let partner_x = partner.map(|p| x_of(&world, p));
+            match x_of(&world, *entity) as usize {
+                0 => assert_eq!((neighbor_xs, partner_x), (vec![1.], Some(1.))),
+                1 => assert_eq!((neighbor_xs, partner_x), (vec![0., 2.], Some(0.))),
+                _ => assert_eq!((neighbor_xs, partner_x), (vec![1.], None)),
+            }
+        }
+
+        let mut edges: Vec<(f32, f32)> = world.query::<&Edge>().iter(&world)
+            .map(|edge| (x_of(&world, edge.source), x_of(&world, edge.target)))
+            .collect();
+        edges.sort_by(|a, b| a.0.total_cmp(&b.0));
+        assert_eq!(edges, vec![(0., 1.), (1., 2.)]);
+        assert_eq!(*world.resource::<State<Phases>>().get(), Phases::Graph);
+    }
+}
@@ -0,0 +1,425 @@
+use std::fs;
+
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{boundary::{wrapped_offset, Boundary}, centrality::{Centrality, CentralityMeasure}, community::Community, graph::{connected_components, Adjacency, Dot, Edge, Neighbors}, phases::DotAttributes, physics::{Acceleration, PhysicsConfig}};
+
+/// Where the style mapping rules are loaded from at startup and saved to from the UI
+pub const STYLE_CONFIG_PATH: &str = "style.ron";
+pub const DOT_RADIUS: f32 = 4.0;
+/// The radius of the largest dot when dot size is mapped to something
+const MAX_DOT_RADIUS: f32 = 10.0;
+/// How many component colours are listed in the legend before the rest are summarized
+const LEGEND_MAX_CATEGORIES: usize = 8;
+/// The strain (relative change in length) at which the heatmap's colours saturate
+const HEATMAP_STRAIN_RANGE: f32 = 0.5;
+
+/// How a single Dot is drawn
+#[derive(Component, Clone)]
+pub struct DotStyle {
+    pub color: Color,
+    pub radius: f32,
+}
+
+impl Default for DotStyle {
+    fn default() -> Self {
+        DotStyle {
+            color: Color::WHITE,
+            radius: DOT_RADIUS,
+        }
+    }
+}
+
+/// How a single Edge is drawn
+#[derive(Component, Clone)]
+pub struct EdgeStyle {
+    pub color: Color,
+}
+
+impl Default for EdgeStyle {
+    fn default() -> Self {
+        EdgeStyle {
+            color: Color::WHITE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DotColorBy {
+    Uniform,
+    Degree,
+    /// Every connected component gets its own colour
+    Component,
+    /// Every detected community gets its own colour
+    Community,
+    Centrality(CentralityMeasure),
+    /// A value imported for each Dot, from `DotSpawnConfig::attributes_path`
+    Attribute(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DotSizeBy {
+    Uniform,
+    Degree,
+    Centrality(CentralityMeasure),
+    Attribute(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EdgeColorBy {
+    Uniform,
+    Length,
+    /// How stretched or compressed the edge's spring is relative to its resting length
+    Strain,
+}
+
+/// Rules mapping graph properties to dot and edge styles
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StyleConfig {
+    pub dot_color_by: DotColorBy,
+    pub dot_size_by: DotSizeBy,
+    pub edge_color_by: EdgeColorBy,
+    pub dot_color: Color,
+    pub edge_color: Color,
+    pub dot_radius: f32,
+    pub max_dot_radius: f32,
+    /// Overrides the colours above: edges by strain and dots by acceleration
+    pub heatmap: bool,
+    pub heatmap_strain_range: f32,
+}
+
+impl Default for StyleConfig {
+    fn default() -> Self {
+        StyleConfig {
+            dot_color_by: DotColorBy::Uniform,
+            dot_size_by: DotSizeBy::Uniform,
+            edge_color_by: EdgeColorBy::Uniform,
+            dot_color: Color::WHITE,
+            edge_color: Color::WHITE,
+            dot_radius: DOT_RADIUS,
+            max_dot_radius: MAX_DOT_RADIUS,
+            heatmap: false,
+            heatmap_strain_range: HEATMAP_STRAIN_RANGE,
+        }
+    }
+}
+
+impl StyleConfig {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        ron::from_str(&contents).map_err(|e| e.to_string())
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorRamp {
+    /// Blue to yellow to red, for values with no natural midpoint
+    Sequential,
+    /// Blue to white to red, for values which are meaningful on either side of the middle
+    Diverging,
+}
+
+impl ColorRamp {
+    pub fn sample(&self, t: f32) -> Color {
+        match self {
+            ColorRamp::Sequential => color_ramp(t),
+            ColorRamp::Diverging => diverging_color_ramp(t),
+        }
+    }
+}
+
+pub enum LegendScale {
+    None,
+    /// A continuous colour ramp between two values
+    Ramp { label: String, min: f32, max: f32, ramp: ColorRamp },
+    Categories(Vec<(String, Color)>),
+}
+
+/// What the current styles mean, for display in the legend
+#[derive(Resource)]
+pub struct Legend {
+    pub dot_color: LegendScale,
+    pub dot_size: LegendScale,
+    pub edge_color: LegendScale,
+}
+
+impl Default for Legend {
+    fn default() -> Self {
+        Legend {
+            dot_color: LegendScale::None,
+            dot_size: LegendScale::None,
+            edge_color: LegendScale::None,
+        }
+    }
+}
+
+/// Maps `t` from 0 to 1 onto a blue to yellow to red colour ramp
+pub fn color_ramp(t: f32) -> Color {
+    const STOPS: [(f32, f32, f32); 5] = [
+        (0.23, 0.30, 0.75),
+        (0.35, 0.70, 0.90),
+        (0.95, 0.90, 0.35),
+        (0.95, 0.55, 0.20),
+        (0.85, 0.15, 0.15),
+    ];
+    let t = t.clamp(0., 1.) * (STOPS.len() - 1) as f32;
+    let i = (t.floor() as usize).min(STOPS.len() - 2);
+    let f = t - i as f32;
+    let (a, b) = (STOPS[i], STOPS[i + 1]);
+    Color::rgb(a.0 + (b.0 - a.0) * f, a.1 + (b.1 - a.1) * f, a.2 + (b.2 - a.2) * f)
+}
+
+/// Maps `t` from 0 to 1 onto a blue to white to red colour ramp, white being 0.5
+pub fn diverging_color_ramp(t: f32) -> Color {
+    const LOW: Vec3 = Vec3::new(0.23, 0.30, 0.75);
+    const MIDDLE: Vec3 = Vec3::new(0.95, 0.95, 0.95);
+    const HIGH: Vec3 = Vec3::new(0.85, 0.15, 0.15);
+    let t = t.clamp(0., 1.);
+    let c = if t < 0.5 { LOW.lerp(MIDDLE, t * 2.) } else { MIDDLE.lerp(HIGH, t * 2. - 1.) };
+    Color::rgb(c.x, c.y, c.z)
+}
+
+/// A distinct colour for each category, spread around the hue wheel by the golden angle
+pub fn category_color(i: usize) -> Color {
+    Color::hsl((i as f32 * 137.508) % 360., 0.7, 0.6)
+}
+
+/// A legend entry for each of `count` categories, summarizing the ones past the first few
+fn category_legend(name: &str, count: usize) -> LegendScale {
+    let mut categories: Vec<(String, Color)> = (0..count.min(LEGEND_MAX_CATEGORIES))
+        .map(|c| (format!("{} {}", name, c + 1), category_color(c)))
+        .collect();
+    if count > LEGEND_MAX_CATEGORIES {
+        categories.push((format!("... {} more", count - LEGEND_MAX_CATEGORIES), Color::GRAY));
+    }
+    LegendScale::Categories(categories)
+}
+
+/// Maps `value` within `min..=max` onto 0 to 1, or 0 if the range is empty
+fn normalize(value: f32, min: f32, max: f32) -> f32 {
+    if max > min { (value - min) / (max - min) } else { 0. }
+}
+
+/// `values` along with the smallest and largest of them
+fn with_range(values: Vec<f32>) -> (Vec<f32>, f32, f32) {
+    let (min, max) = values.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    (values, min, max)
+}
+
+pub fn load_style_config(mut style_config: ResMut<StyleConfig>) {
+    if !std::path::Path::new(STYLE_CONFIG_PATH).exists() {
+        return;
+    }
+    match StyleConfig::load(STYLE_CONFIG_PATH) {
+        Ok(loaded) => *style_config = loaded,
+        Err(e) => warn!("could not load style config \"{}\": {}", STYLE_CONFIG_PATH, e),
+    }
+}
+
+/// Everything about a Dot its style can be mapped from
+type StyledDot<'a> = (Entity, &'a Neighbors, &'a Transform, &'a mut DotStyle, Option<&'a Community>, Option<&'a Centrality>, Option<&'a DotAttributes>);
+
+/// Anything a style can be mapped from, other than where Dots are
+type Restyled = Or<(Changed<Neighbors>, Changed<Community>, Changed<Centrality>, Changed<DotAttributes>, Changed<Edge>)>;
+
+/// Whether the graph has changed in a way that needs it restyled
+#[derive(SystemParam)]
+pub struct GraphChanges<'w, 's> {
+    changed: Query<'w, 's, (), Restyled>,
+    removed: RemovedComponents<'w, 's, Dot>,
+}
+
+impl GraphChanges<'_, '_> {
+    fn any(&mut self) -> bool {
+        // read every removal, so they aren't seen again next time
+        let removed = self.removed.read().count() > 0;
+        removed || !self.changed.is_empty()
+    }
+}
+
+/// Applies the mapping rules in `StyleConfig` to every Dot's `DotStyle` and every Edge's
+/// `EdgeStyle`. Styles are only recomputed when the rules or the graph change, except for edge
+/// colours mapped from lengths, which change whenever Dots move.
+pub fn apply_styles(
+    mut dots_q: Query<StyledDot, With<Dot>>,
+    mut edges_q: Query<(&Edge, &mut EdgeStyle)>,
+    mut graph_changes: GraphChanges,
+    style_config: Res<StyleConfig>,
+    physics_config: Res<PhysicsConfig>,
+    boundary: Res<Boundary>,
+    mut legend: ResMut<Legend>,
+) {
+    let restyle = graph_changes.any() || style_config.is_changed();
+    if !restyle && style_config.edge_color_by == EdgeColorBy::Uniform {
+        return;
+    }
+
+    if restyle {
+        apply_dot_styles(&mut dots_q, &style_config, &mut legend);
+    }
+
+    // edge colours
+    let positions: HashMap<Entity, Vec2> = dots_q.iter().map(|(eid, _, tf, ..)| (eid, tf.translation.xy())).collect();
+    let wrap = boundary.wrap_bounds();
+    let edge_value = |edge: &Edge| -> Option<f32> {
+        let length = wrapped_offset(*positions.get(&edge.source)?, *positions.get(&edge.target)?, wrap).length();
+        match style_config.edge_color_by {
+            EdgeColorBy::Uniform => None,
+            EdgeColorBy::Length => Some(length),
+            EdgeColorBy::Strain => {
+                let resting_length = physics_config.edge_resting_length(edge);
+                Some((length - resting_length) / resting_length.max(f32::EPSILON))
+            }
+        }
+    };
+
+    let values: Vec<f32> = edges_q.iter().filter_map(|(edge, _)| edge_value(edge)).collect();
+    let (min, max) = values.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+
+    legend.edge_color = match style_config.edge_color_by {
+        EdgeColorBy::Uniform => LegendScale::None,
+        EdgeColorBy::Length => LegendScale::Ramp { label: "Length".into(), min, max, ramp: ColorRamp::Sequential },
+        EdgeColorBy::Strain => LegendScale::Ramp { label: "Strain".into(), min, max, ramp: ColorRamp::Sequential },
+    };
+
+    for (edge, mut style) in edges_q.iter_mut() {
+        let color = match edge_value(edge) {
+            Some(v) => color_ramp(normalize(v, min, max)),
+            // an edge's own colour is only overridden by mapping edge colours to something
+            None => edge.color.unwrap_or(style_config.edge_color),
+        };
+        if style.color != color {
+            style.color = color;
+        }
+    }
+}
+
+/// Maps the rules for Dots onto every `DotStyle`, and describes them in the legend
+fn apply_dot_styles(dots_q: &mut Query<StyledDot, With<Dot>>, style_config: &StyleConfig, legend: &mut Legend) {
+    let adjacency = Adjacency::from_neighbors(dots_q.iter().map(|(eid, neighbors, ..)| (eid, neighbors)));
+    let degrees: Vec<f32> = adjacency.adjacent.iter().map(|a| a.len() as f32).collect();
+    let (min_degree, max_degree) = degrees.iter().fold((f32::INFINITY, 0f32), |(lo, hi), &d| (lo.min(d), hi.max(d)));
+    // Dots without a value yet count as 0
+    let centrality = |measure: CentralityMeasure| -> (Vec<f32>, f32, f32) {
+        with_range(adjacency.entities.iter()
+            .map(|eid| dots_q.get(*eid).ok().and_then(|(.., centrality, _)| centrality).map_or(0., |c| measure.value(c)))
+            .collect())
+    };
+    let attribute = |name: &str| -> (Vec<f32>, f32, f32) {
+        with_range(adjacency.entities.iter()
+            .map(|eid| dots_q.get(*eid).ok().and_then(|(.., attributes)| attributes?.0.get(name).copied()).unwrap_or(0.))
+            .collect())
+    };
+
+    let dot_colors: Vec<Color> = match &style_config.dot_color_by {
+        DotColorBy::Uniform => {
+            legend.dot_color = LegendScale::None;
+            vec![style_config.dot_color; adjacency.len()]
+        }
+        DotColorBy::Degree => {
+            legend.dot_color = LegendScale::Ramp { label: "Degree".into(), min: min_degree, max: max_degree, ramp: ColorRamp::Sequential };
+            degrees.iter().map(|&d| color_ramp(normalize(d, min_degree, max_degree))).collect()
+        }
+        DotColorBy::Component => {
+            let components = connected_components(&adjacency);
+            legend.dot_color = category_legend("Component", components.iter().max().map_or(0, |c| c + 1));
+            components.iter().map(|&c| category_color(c)).collect()
+        }
+        DotColorBy::Community => {
+            // Dots without a community yet are drawn in the uniform colour
+            let communities: Vec<Option<usize>> = adjacency.entities.iter()
+                .map(|eid| dots_q.get(*eid).ok().and_then(|(.., community, _, _)| community).map(|c| c.0))
+                .collect();
+            legend.dot_color = category_legend("Community", communities.iter().flatten().max().map_or(0, |c| c + 1));
+            communities.iter().map(|c| c.map_or(style_config.dot_color, category_color)).collect()
+        }
+        DotColorBy::Centrality(measure) => {
+            let (values, min, max) = centrality(*measure);
+            legend.dot_color = LegendScale::Ramp { label: measure.name().into(), min, max, ramp: ColorRamp::Sequential };
+            values.iter().map(|&v| color_ramp(normalize(v, min, max))).collect()
+        }
+        DotColorBy::Attribute(name) => {
+            let (values, min, max) = attribute(name);
+            legend.dot_color = LegendScale::Ramp { label: name.clone(), min, max, ramp: ColorRamp::Sequential };
+            values.iter().map(|&v| color_ramp(normalize(v, min, max))).collect()
+        }
+    };
+
+    let radius = |t: f32| style_config.dot_radius + (style_config.max_dot_radius - style_config.dot_radius) * t;
+    let dot_radii: Vec<f32> = match &style_config.dot_size_by {
+        DotSizeBy::Uniform => {
+            legend.dot_size = LegendScale::None;
+            vec![style_config.dot_radius; adjacency.len()]
+        }
+        DotSizeBy::Degree => {
+            legend.dot_size = LegendScale::Ramp { label: "Degree".into(), min: min_degree, max: max_degree, ramp: ColorRamp::Sequential };
+            degrees.iter().map(|&d| radius(normalize(d, min_degree, max_degree))).collect()
+        }
+        DotSizeBy::Centrality(measure) => {
+            let (values, min, max) = centrality(*measure);
+            legend.dot_size = LegendScale::Ramp { label: measure.name().into(), min, max, ramp: ColorRamp::Sequential };
+            values.iter().map(|&v| radius(normalize(v, min, max))).collect()
+        }
+        DotSizeBy::Attribute(name) => {
+            let (values, min, max) = attribute(name);
+            legend.dot_size = LegendScale::Ramp { label: name.clone(), min, max, ramp: ColorRamp::Sequential };
+            values.iter().map(|&v| radius(normalize(v, min, max))).collect()
+        }
+    };
+
+    for (i, eid) in adjacency.entities.iter().enumerate() {
+        let mut style = dots_q.get_mut(*eid).unwrap().3;
+        let new_style = DotStyle { color: dot_colors[i], radius: dot_radii[i] };
+        // only touch the component when something changed, so renderers can rely on `Changed`
+        if style.color != new_style.color || style.radius != new_style.radius {
+            *style = new_style;
+        }
+    }
+}
+
+/// Debug colouring for spring tuning: edges by how stretched (red) or compressed (blue) they are
+/// relative to their resting length, and dots by the magnitude of their acceleration. Runs after
+/// `apply_styles` and overrides its colours.
+pub fn apply_heatmap(
+    mut dots_q: Query<(&Transform, &Acceleration, &mut DotStyle), With<Dot>>,
+    mut edges_q: Query<(&Edge, &mut EdgeStyle)>,
+    style_config: Res<StyleConfig>,
+    physics_config: Res<PhysicsConfig>,
+    boundary: Res<Boundary>,
+    mut legend: ResMut<Legend>,
+) {
+    if !style_config.heatmap {
+        return;
+    }
+
+    // accelerations are capped, so the cap is the top of the ramp
+    let max_acceleration = physics_config.acc_cap.max(f32::EPSILON);
+    for (_, acc, mut style) in dots_q.iter_mut() {
+        let color = color_ramp(acc.0.length() / max_acceleration);
+        if style.color != color {
+            style.color = color;
+        }
+    }
+    legend.dot_color = LegendScale::Ramp { label: "Acceleration".into(), min: 0., max: max_acceleration, ramp: ColorRamp::Sequential };
+
+    let range = style_config.heatmap_strain_range.max(f32::EPSILON);
+    let wrap = boundary.wrap_bounds();
+    for (edge, mut style) in edges_q.iter_mut() {
+        let Ok([(source, ..), (target, ..)]) = dots_q.get_many([edge.source, edge.target]) else { continue };
+        let length = wrapped_offset(source.translation.xy(), target.translation.xy(), wrap).length();
+        let resting_length = physics_config.edge_resting_length(edge).max(f32::EPSILON);
+        let strain = (length - resting_length) / resting_length;
+        let color = diverging_color_ramp(0.5 + 0.5 * strain / range);
+        if style.color != color {
+            style.color = color;
+        }
+    }
+    legend.edge_color = LegendScale::Ramp { label: "Strain".into(), min: -range, max: range, ramp: ColorRamp::Diverging };
+}
@@ -0,0 +1,266 @@
+use std::{fmt::Write as _, fs};
+
+use bevy::{ecs::system::RunSystemOnce, log::tracing_subscriber, prelude::*};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{graph::{Dot, GraphSpawnConfig}, headless::headless_app, physics::{PhysicsConfig, Velocity}, quality::{measure_layout_quality, LayoutQualityConfig, LayoutQualityLog, QualityMetric}, Randomness, SEED};
+
+pub const SWEEP_REPORT_PATH: &str = "sweep.csv";
+/// How many frames each run is simulated for, unless the sweep says otherwise
+const SWEEP_STEPS: u32 = 600;
+/// A run counts as settled once the Dots' mean speed stays below this
+const CONVERGED_SPEED: f32 = 0.05;
+
+/// A number in `PhysicsConfig` or `GraphSpawnConfig` that can be swept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SweepField {
+    RepelStrength,
+    SpringCoefficient,
+    SpringRestingLength,
+    WallRepelStrength,
+    VelDampening,
+    VelCap,
+    AccDampening,
+    AccCap,
+    MaxDistance,
+    /// Rounded to the nearest whole number
+    KNearest,
+    Beta,
+}
+
+impl SweepField {
+    pub fn set(&self, physics: &mut PhysicsConfig, graph_spawn: &mut GraphSpawnConfig, value: f32) {
+        match self {
+            SweepField::RepelStrength => physics.repel_strength = value,
+            SweepField::SpringCoefficient => physics.spring_coefficient = value,
+            SweepField::SpringRestingLength => physics.spring_resting_length = value,
+            SweepField::WallRepelStrength => physics.wall_repel_strength = value,
+            SweepField::VelDampening => physics.vel_dampening = value,
+            SweepField::VelCap => physics.vel_cap = value,
+            SweepField::AccDampening => physics.acc_dampening = value,
+            SweepField::AccCap => physics.acc_cap = value,
+            SweepField::MaxDistance => graph_spawn.max_distance = value,
+            SweepField::KNearest => graph_spawn.k_nearest = value.round().max(1.) as usize,
+            SweepField::Beta => graph_spawn.beta = value,
+        }
+    }
+
+    /// The name as a CSV column
+    pub fn column(&self) -> &'static str {
+        match self {
+            SweepField::RepelStrength => "repel_strength",
+            SweepField::SpringCoefficient => "spring_coefficient",
+            SweepField::SpringRestingLength => "spring_resting_length",
+            SweepField::WallRepelStrength => "wall_repel_strength",
+            SweepField::VelDampening => "vel_dampening",
+            SweepField::VelCap => "vel_cap",
+            SweepField::AccDampening => "acc_dampening",
+            SweepField::AccCap => "acc_cap",
+            SweepField::MaxDistance => "max_distance",
+            SweepField::KNearest => "k_nearest",
+            SweepField::Beta => "beta",
+        }
+    }
+}
+
+/// The range of values one field is swept over
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SweepParameter {
+    pub field: SweepField,
+    pub min: f32,
+    pub max: f32,
+    /// How many evenly spaced values to try, in a grid sweep
+    pub count: usize,
+}
+
+impl SweepParameter {
+    fn grid_values(&self) -> Vec<f32> {
+        match self.count {
+            0 => Vec::new(),
+            1 => vec![self.min],
+            count => (0..count).map(|i| self.min + (self.max - self.min) * i as f32 / (count - 1) as f32).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SweepMode {
+    /// Every combination of every parameter's values
+    Grid,
+    /// Values drawn uniformly from every parameter's range
+    Random { samples: usize },
+}
+
+/// What to sweep over, loaded from a RON file. Every combination of values is run once per seed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SweepSpec {
+    pub mode: SweepMode,
+    pub steps: u32,
+    pub seeds: Vec<u64>,
+    /// Seeds the random sampling of values, so a random sweep can be repeated
+    pub sample_seed: u64,
+    pub parameters: Vec<SweepParameter>,
+    /// The configuration the swept values are applied on top of
+    pub physics: PhysicsConfig,
+    pub graph_spawn: GraphSpawnConfig,
+    pub converged_speed: f32,
+}
+
+impl Default for SweepSpec {
+    fn default() -> Self {
+        SweepSpec {
+            mode: SweepMode::Grid,
+            steps: SWEEP_STEPS,
+            seeds: vec![SEED],
+            sample_seed: SEED,
+            parameters: Vec::new(),
+            physics: PhysicsConfig::default(),
+            graph_spawn: GraphSpawnConfig::default(),
+            converged_speed: CONVERGED_SPEED,
+        }
+    }
+}
+
+impl SweepSpec {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        ron::from_str(&contents).map_err(|e| e.to_string())
+    }
+
+    /// Every set of values to run, one value per parameter, in the same order as `parameters`
+    pub fn samples(&self) -> Vec<Vec<f32>> {
+        match self.mode {
+            SweepMode::Grid => self.parameters.iter().fold(vec![Vec::new()], |samples, parameter| {
+                samples.iter()
+                    .flat_map(|sample| parameter.grid_values().into_iter().map(move |value| {
+                        let mut sample = sample.clone();
+                        sample.push(value);
+                        sample
+                    }))
+                    .collect()
+            }),
+            SweepMode::Random { samples } => {
+                let mut rng = StdRng::seed_from_u64(self.sample_seed);
+                (0..samples)
+                    .map(|_| self.parameters.iter()
+                        .map(|parameter| if parameter.max > parameter.min { rng.gen_range(parameter.min..=parameter.max) } else { parameter.min })
+                        .collect())
+                    .collect()
+            }
+        }
+    }
+}
+
+/// What one run of a sweep ended up with
+pub struct SweepResult {
+    pub seed: u64,
+    pub values: Vec<f32>,
+    /// The value of every `QualityMetric` at the end, in the order of `QualityMetric::ALL`
+    pub quality: Vec<f32>,
+    /// The Dots' mean speed on the last frame
+    pub final_speed: f32,
+    /// The frame after which the mean speed stayed below the threshold, if it did by the end
+    pub converged_at: Option<u32>,
+}
+
+fn mean_speed(world: &mut World) -> f32 {
+    let speeds: Vec<f32> = world.query_filtered::<&Velocity, With<Dot>>().iter(world).map(|vel| vel.0.length()).collect();
+    speeds.iter().sum::<f32>() / speeds.len().max(1) as f32
+}
+
+/// Runs the simulation headlessly with one set of values and seed
+fn run_one(spec: &SweepSpec, values: &[f32], seed: u64) -> SweepResult {
+    let mut physics = spec.physics.clone();
+    let mut graph_spawn = spec.graph_spawn.clone();
+    for (parameter, &value) in spec.parameters.iter().zip(values) {
+        parameter.field.set(&mut physics, &mut graph_spawn, value);
+    }
+
+    // logging can only be set up once per process, which `run_sweep` has done
+    let mut app = headless_app(false);
+    app.insert_resource(physics)
+        .insert_resource(graph_spawn)
+        .insert_resource(Randomness(StdRng::seed_from_u64(seed)));
+    app.finish();
+    app.cleanup();
+
+    let mut converged_at = None;
+    let mut final_speed = 0.;
+    for step in 0..spec.steps {
+        app.update();
+        final_speed = mean_speed(&mut app.world);
+        match (final_speed < spec.converged_speed, converged_at) {
+            (true, None) => converged_at = Some(step),
+            (false, Some(_)) => converged_at = None,
+            _ => {}
+        }
+    }
+
+    app.world.resource_mut::<LayoutQualityConfig>().requested = true;
+    app.world.run_system_once(measure_layout_quality);
+    let quality = app.world.resource::<LayoutQualityLog>().latest().cloned().unwrap_or_default();
+
+    SweepResult {
+        seed,
+        values: values.to_vec(),
+        quality: QualityMetric::ALL.iter().map(|metric| metric.value(&quality)).collect(),
+        final_speed,
+        converged_at,
+    }
+}
+
+fn report_csv(spec: &SweepSpec, results: &[SweepResult]) -> String {
+    let mut csv = String::from("run,seed");
+    for parameter in spec.parameters.iter() {
+        let _ = write!(csv, ",{}", parameter.field.column());
+    }
+    for metric in QualityMetric::ALL {
+        let _ = write!(csv, ",{}", metric.column());
+    }
+    csv.push_str(",final_speed,converged_at\n");
+
+    for (run, result) in results.iter().enumerate() {
+        let _ = write!(csv, "{},{}", run, result.seed);
+        for value in result.values.iter().chain(result.quality.iter()) {
+            let _ = write!(csv, ",{}", value);
+        }
+        let converged_at = result.converged_at.map_or(String::new(), |step| step.to_string());
+        let _ = writeln!(csv, ",{},{}", result.final_speed, converged_at);
+    }
+    csv
+}
+
+/// Runs every sample of the sweep in `spec_path` with every seed, and writes a CSV report of how
+/// each run turned out. Exits with an error if the sweep can't be loaded or reported on
+pub fn run_sweep(spec_path: &str, report_path: &str) {
+    // every run has its own app, and none of them can set up logging as it can only be done once
+    let _ = tracing_subscriber::fmt().with_writer(std::io::stderr).try_init();
+
+    let spec = match SweepSpec::load(spec_path) {
+        Ok(spec) => spec,
+        Err(e) => {
+            error!("could not load sweep from {}: {}", spec_path, e);
+            std::process::exit(1);
+        }
+    };
+
+    let samples = spec.samples();
+    let runs = samples.len() * spec.seeds.len();
+    let mut results = Vec::with_capacity(runs);
+    for values in samples.iter() {
+        for &seed in spec.seeds.iter() {
+            info!("sweep run {}/{}: {:?} with seed {}", results.len() + 1, runs, values, seed);
+            results.push(run_one(&spec, values, seed));
+        }
+    }
+
+    match fs::write(report_path, report_csv(&spec, &results)) {
+        Ok(()) => info!("wrote {} sweep runs to {}", results.len(), report_path),
+        Err(e) => {
+            error!("could not write sweep report to {}: {}", report_path, e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -1,14 +1,18 @@
-use bevy::ecs::{schedule::NextState, system::ResMut};
+use std::collections::BTreeSet;
+
+use bevy::{core::Name, ecs::{change_detection::DetectChangesMut, entity::Entity, query::With, schedule::NextState, system::{Commands, Local, Query, Res, ResMut, Resource}, world::World}, input::{mouse::MouseButton, ButtonInput}, log::{error, warn}, transform::components::Transform};
 use bevy_egui::{egui, EguiContexts};
 
-use crate::{graph::{ComputeNeighborsMethod, GraphSpawnConfig}, phases::{Phases, SpawnMethod}, physics::PhysicsConfig};
+use crate::{analytics::GraphStats, boundary::{Boundary, BoundaryShape, WallBehaviour}, centrality::{Centrality, CentralityMeasure}, community::{CommunityConfig, CommunityMethod, CommunityStats}, export::{ExportRequests, EXPORT_PNG_PATH, EXPORT_SCREENSHOT_PATH, EXPORT_SVG_PATH}, graph::{ComputeNeighborsMethod, Dot, Neighbors, EdgeMode, EdgeWeightSource, GraphSpawnConfig}, labels::{dot_label_text, LabelConfig}, layout::{StressLayoutConfig, StressLayoutMode}, obstacles::{FieldFalloff, ForceField, Obstacle, ObstacleBehaviour, Obstacles}, pathfinding::{dot_at, PathAlgorithm, PathCost, PathSelection, PathfindingConfig}, phases::{DotAttributes, DotSpawnConfig, Phases, SpawnMethod}, physics::{Charge, GravityFalloff, Mass, PhysicsConfig, WeightFunction}, preset::{Preset, PRESET_PATH}, quality::{LayoutQualityConfig, LayoutQualityLog, QualityMetric}, record::{RecordConfig, RecordFormat, Recorder}, replay::{Replay, ReplayMode}, snapshot::{SnapshotRequests, SNAPSHOT_PATH}, render::{RenderConfig, RenderMode}, style::{DotColorBy, DotSizeBy, DotStyle, EdgeColorBy, Legend, LegendScale, StyleConfig, STYLE_CONFIG_PATH}, MousePosition};
 
 pub fn ui_tweak_panel(
     mut contexts: EguiContexts,
     mut next_state: ResMut<NextState<Phases>>,
     mut physics_config: ResMut<PhysicsConfig>,
     mut spawn_method: ResMut<SpawnMethod>,
+    mut dot_spawn_config: ResMut<DotSpawnConfig>,
     mut graph_spawn_config: ResMut<GraphSpawnConfig>,
+    mut stress_config: ResMut<StressLayoutConfig>,
 ) {
     egui::Window::new("Tweaks").show(contexts.ctx_mut(), |ui| {
         egui::ComboBox::from_label("Spawn Method")
@@ -16,13 +20,42 @@ pub fn ui_tweak_panel(
             .show_ui(ui, |ui| {
                 ui.selectable_value(spawn_method.as_mut(), SpawnMethod::Random, "Random");
                 ui.selectable_value(spawn_method.as_mut(), SpawnMethod::Grid, "Grid");
+                ui.selectable_value(spawn_method.as_mut(), SpawnMethod::Spectral, "Spectral");
+                ui.selectable_value(spawn_method.as_mut(), SpawnMethod::Multilevel, "Multilevel");
+                ui.selectable_value(spawn_method.as_mut(), SpawnMethod::PoissonDisk, "PoissonDisk");
+                ui.selectable_value(spawn_method.as_mut(), SpawnMethod::Hexagonal, "Hexagonal");
+                ui.selectable_value(spawn_method.as_mut(), SpawnMethod::Rings, "Rings");
+                ui.selectable_value(spawn_method.as_mut(), SpawnMethod::ImageMask, "ImageMask");
             });
+
+        match *spawn_method {
+            SpawnMethod::PoissonDisk => {
+                ui.add(egui::Slider::new(&mut dot_spawn_config.poisson_min_distance, 5.0..=100.0).text("Min Distance"));
+            }
+            SpawnMethod::ImageMask => {
+                ui.horizontal(|ui| {
+                    ui.label("Image Path");
+                    ui.text_edit_singleline(&mut dot_spawn_config.image_mask_path);
+                });
+                ui.add(egui::Slider::new(&mut dot_spawn_config.brightness_threshold, 0.0..=1.0).text("Brightness Threshold"));
+            }
+            _ => {}
+        }
+        ui.horizontal(|ui| {
+            ui.label("Attributes Path");
+            ui.text_edit_singleline(&mut dot_spawn_config.attributes_path);
+        });
         
         egui::ComboBox::from_label("Compute Neighbors Method")
             .selected_text(format!("{:?}", graph_spawn_config.compute_neighbors_method))
             .show_ui(ui, |ui| {
                 ui.selectable_value(&mut graph_spawn_config.compute_neighbors_method, ComputeNeighborsMethod::KNearest, "KNearest");
                 ui.selectable_value(&mut graph_spawn_config.compute_neighbors_method, ComputeNeighborsMethod::Distance, "Distance");
+                ui.selectable_value(&mut graph_spawn_config.compute_neighbors_method, ComputeNeighborsMethod::Delaunay, "Delaunay");
+                ui.selectable_value(&mut graph_spawn_config.compute_neighbors_method, ComputeNeighborsMethod::Gabriel, "Gabriel");
+                ui.selectable_value(&mut graph_spawn_config.compute_neighbors_method, ComputeNeighborsMethod::RelativeNeighborhood, "RelativeNeighborhood");
+                ui.selectable_value(&mut graph_spawn_config.compute_neighbors_method, ComputeNeighborsMethod::MinimumSpanningTree, "MinimumSpanningTree");
+                ui.selectable_value(&mut graph_spawn_config.compute_neighbors_method, ComputeNeighborsMethod::BetaSkeleton, "BetaSkeleton");
             });
 
         match graph_spawn_config.compute_neighbors_method {
@@ -32,6 +65,31 @@ pub fn ui_tweak_panel(
             ComputeNeighborsMethod::Distance => {
                 ui.add(egui::Slider::new(&mut graph_spawn_config.max_distance, 0.0..=150.0).text("Max Distance"));
             }
+            ComputeNeighborsMethod::BetaSkeleton => {
+                ui.add(egui::Slider::new(&mut graph_spawn_config.beta, 0.1..=3.0).text("Beta"));
+            }
+            _ => {}
+        }
+
+        egui::ComboBox::from_label("Edge Mode")
+            .selected_text(format!("{:?}", graph_spawn_config.edge_mode))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut graph_spawn_config.edge_mode, EdgeMode::Undirected, "Undirected");
+                ui.selectable_value(&mut graph_spawn_config.edge_mode, EdgeMode::Directed, "Directed");
+            });
+
+        egui::ComboBox::from_label("Edge Weights")
+            .selected_text(format!("{:?}", graph_spawn_config.edge_weight_source))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut graph_spawn_config.edge_weight_source, EdgeWeightSource::Uniform, "Uniform");
+                ui.selectable_value(&mut graph_spawn_config.edge_weight_source, EdgeWeightSource::Distance, "Distance");
+                ui.selectable_value(&mut graph_spawn_config.edge_weight_source, EdgeWeightSource::Imported, "Imported");
+            });
+        if graph_spawn_config.edge_weight_source == EdgeWeightSource::Imported {
+            ui.horizontal(|ui| {
+                ui.label("Edge Weights Path");
+                ui.text_edit_singleline(&mut graph_spawn_config.edge_weights_path);
+            });
         }
 
         if ui.button("Reset").clicked() {
@@ -50,5 +108,811 @@ pub fn ui_tweak_panel(
         ui.add(egui::Slider::new(&mut physics_config.acc_dampening, 0.0..=1.0).text("Acceleration Dampening"));
         ui.add(egui::Slider::new(&mut physics_config.acc_cap, 0.0..=200.0).text("Acceleration Cap"));
 
+        weight_function_combo_box(ui, "Spring Coefficient By Weight", &mut physics_config.spring_coefficient_by_weight);
+        weight_function_combo_box(ui, "Spring Resting Length By Weight", &mut physics_config.spring_resting_length_by_weight);
+        weight_function_combo_box(ui, "Mass By Degree", &mut physics_config.mass_by_degree);
+        weight_function_combo_box(ui, "Charge By Degree", &mut physics_config.charge_by_degree);
+
+        ui.add(egui::Slider::new(&mut physics_config.center_gravity, 0.0..=0.05).text("Center Gravity"));
+        if physics_config.center_gravity > 0. {
+            egui::ComboBox::from_label("Center Gravity Falloff")
+                .selected_text(format!("{:?}", physics_config.center_gravity_falloff))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut physics_config.center_gravity_falloff, GravityFalloff::Linear, "Linear");
+                    ui.selectable_value(&mut physics_config.center_gravity_falloff, GravityFalloff::Constant, "Constant");
+                });
+        }
+        ui.horizontal(|ui| {
+            ui.label("Gravity");
+            ui.add(egui::DragValue::new(&mut physics_config.gravity.x).speed(0.05).prefix("x "));
+            ui.add(egui::DragValue::new(&mut physics_config.gravity.y).speed(0.05).prefix("y "));
+        });
+        ui.add(egui::Slider::new(&mut physics_config.component_centering, 0.0..=0.05).text("Component Centering"));
+
+        ui.checkbox(&mut physics_config.collisions, "Collisions");
+        if physics_config.collisions {
+            ui.add(egui::Slider::new(&mut physics_config.collision_radius, 0.5..=30.0).text("Collision Radius"));
+            ui.add(egui::Slider::new(&mut physics_config.restitution, 0.0..=1.0).text("Restitution"));
+        }
+
+        ui.separator();
+
+        egui::ComboBox::from_label("Stress Layout")
+            .selected_text(format!("{:?}", stress_config.mode))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut stress_config.mode, StressLayoutMode::Off, "Off");
+                ui.selectable_value(&mut stress_config.mode, StressLayoutMode::OneShot, "OneShot");
+                ui.selectable_value(&mut stress_config.mode, StressLayoutMode::Incremental, "Incremental");
+            });
+
+        match stress_config.mode {
+            StressLayoutMode::Off => {}
+            StressLayoutMode::OneShot => {
+                ui.add(egui::Slider::new(&mut stress_config.edge_length, 10.0..=200.0).text("Edge Length"));
+                ui.add(egui::Slider::new(&mut stress_config.one_shot_iterations, 1..=1000).text("Iterations"));
+            }
+            StressLayoutMode::Incremental => {
+                ui.add(egui::Slider::new(&mut stress_config.edge_length, 10.0..=200.0).text("Edge Length"));
+                ui.add(egui::Slider::new(&mut stress_config.iterations_per_frame, 1..=20).text("Iterations Per Frame"));
+            }
+        }
+    });
+}
+
+fn weight_function_combo_box(ui: &mut egui::Ui, label: &str, weight_function: &mut WeightFunction) {
+    egui::ComboBox::from_label(label)
+        .selected_text(format!("{:?}", weight_function))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(weight_function, WeightFunction::Constant, "Constant");
+            ui.selectable_value(weight_function, WeightFunction::Proportional, "Proportional");
+            ui.selectable_value(weight_function, WeightFunction::Inverse, "Inverse");
+            ui.selectable_value(weight_function, WeightFunction::Logarithmic, "Logarithmic");
+        });
+}
+
+/// How Dots, Edges and labels are drawn
+pub fn ui_style_panel(
+    mut contexts: EguiContexts,
+    dots_q: Query<&DotAttributes, With<Dot>>,
+    mut render_config: ResMut<RenderConfig>,
+    mut style_config: ResMut<StyleConfig>,
+    mut label_config: ResMut<LabelConfig>,
+) {
+    // styles are only reapplied when the config changes, so edit a copy and only write it back
+    // when something was actually edited
+    let mut style = style_config.clone();
+    let attribute_names: BTreeSet<&String> = dots_q.iter().flat_map(|attributes| attributes.0.keys()).collect();
+
+    egui::Window::new("Style").show(contexts.ctx_mut(), |ui| {
+        egui::ComboBox::from_label("Render Mode")
+            .selected_text(format!("{:?}", render_config.mode))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut render_config.mode, RenderMode::Meshes, "Meshes");
+                ui.selectable_value(&mut render_config.mode, RenderMode::Gizmos, "Gizmos");
+            });
+        ui.checkbox(&mut render_config.show_edge_weights, "Show Edge Weights");
+
+        egui::ComboBox::from_label("Dot Colour")
+            .selected_text(format!("{:?}", style.dot_color_by))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut style.dot_color_by, DotColorBy::Uniform, "Uniform");
+                ui.selectable_value(&mut style.dot_color_by, DotColorBy::Degree, "Degree");
+                ui.selectable_value(&mut style.dot_color_by, DotColorBy::Component, "Component");
+                ui.selectable_value(&mut style.dot_color_by, DotColorBy::Community, "Community");
+                for measure in CentralityMeasure::ALL {
+                    ui.selectable_value(&mut style.dot_color_by, DotColorBy::Centrality(measure), measure.name());
+                }
+                for name in attribute_names.iter() {
+                    ui.selectable_value(&mut style.dot_color_by, DotColorBy::Attribute((*name).clone()), name.as_str());
+                }
+            });
+        egui::ComboBox::from_label("Dot Size")
+            .selected_text(format!("{:?}", style.dot_size_by))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut style.dot_size_by, DotSizeBy::Uniform, "Uniform");
+                ui.selectable_value(&mut style.dot_size_by, DotSizeBy::Degree, "Degree");
+                for measure in CentralityMeasure::ALL {
+                    ui.selectable_value(&mut style.dot_size_by, DotSizeBy::Centrality(measure), measure.name());
+                }
+                for name in attribute_names.iter() {
+                    ui.selectable_value(&mut style.dot_size_by, DotSizeBy::Attribute((*name).clone()), name.as_str());
+                }
+            });
+        egui::ComboBox::from_label("Edge Colour")
+            .selected_text(format!("{:?}", style.edge_color_by))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut style.edge_color_by, EdgeColorBy::Uniform, "Uniform");
+                ui.selectable_value(&mut style.edge_color_by, EdgeColorBy::Length, "Length");
+                ui.selectable_value(&mut style.edge_color_by, EdgeColorBy::Strain, "Strain");
+            });
+        ui.add(egui::Slider::new(&mut style.dot_radius, 1.0..=20.0).text("Dot Radius"));
+        if style.dot_size_by != DotSizeBy::Uniform {
+            ui.add(egui::Slider::new(&mut style.max_dot_radius, 1.0..=30.0).text("Max Dot Radius"));
+        }
+
+        ui.checkbox(&mut style.heatmap, "Strain Heatmap");
+        if style.heatmap {
+            ui.add(egui::Slider::new(&mut style.heatmap_strain_range, 0.05..=2.0).text("Heatmap Strain Range"));
+        }
+
+        ui.checkbox(&mut label_config.show_dot_labels, "Dot Labels");
+        ui.checkbox(&mut label_config.show_edge_labels, "Edge Labels");
+        if label_config.show_dot_labels || label_config.show_edge_labels {
+            ui.add(egui::Slider::new(&mut label_config.font_size, 6.0..=32.0).text("Label Font Size"));
+            ui.add(egui::Slider::new(&mut label_config.max_zoom_out, 0.2..=5.0).text("Hide Labels Past Zoom"));
+            ui.checkbox(&mut label_config.avoid_overlap, "Avoid Label Overlap");
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Save Style").clicked() {
+                if let Err(e) = style.save(STYLE_CONFIG_PATH) {
+                    error!("could not save style config \"{}\": {}", STYLE_CONFIG_PATH, e);
+                }
+            }
+            if ui.button("Load Style").clicked() {
+                match StyleConfig::load(STYLE_CONFIG_PATH) {
+                    Ok(loaded) => style = loaded,
+                    Err(e) => warn!("could not load style config \"{}\": {}", STYLE_CONFIG_PATH, e),
+                }
+            }
+        });
+    });
+
+    style_config.set_if_neq(style);
+}
+
+/// Saves pictures, snapshots and recordings of the graph
+pub fn ui_output_panel(
+    mut contexts: EguiContexts,
+    mut export_requests: ResMut<ExportRequests>,
+    mut snapshot_requests: ResMut<SnapshotRequests>,
+    mut record_config: ResMut<RecordConfig>,
+    mut recorder: ResMut<Recorder>,
+) {
+    // deterministic mode is only switched when the config changes, so edit a copy and only write
+    // it back when something was actually edited
+    let mut config = record_config.clone();
+
+    egui::Window::new("Output").show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            if ui.button("Export SVG").clicked() {
+                export_requests.svg = Some(EXPORT_SVG_PATH.to_owned());
+            }
+            if ui.button("Export PNG").clicked() {
+                export_requests.png = Some(EXPORT_PNG_PATH.to_owned());
+            }
+            if ui.button("Screenshot").clicked() {
+                export_requests.screenshot = Some(EXPORT_SCREENSHOT_PATH.to_owned());
+            }
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Save Snapshot").clicked() {
+                snapshot_requests.save = Some(SNAPSHOT_PATH.to_owned());
+            }
+            if ui.button("Load Snapshot").clicked() {
+                snapshot_requests.load = Some(SNAPSHOT_PATH.to_owned());
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Record To");
+            ui.add_enabled(!recorder.recording, egui::TextEdit::singleline(&mut config.directory));
+        });
+        egui::ComboBox::from_label("Record Format")
+            .selected_text(format!("{:?}", config.format))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut config.format, RecordFormat::PngSequence, "PngSequence");
+                ui.selectable_value(&mut config.format, RecordFormat::Apng, "Apng");
+            });
+        ui.add(egui::Slider::new(&mut config.frame_scale, 0.1..=1.0).text("Frame Scale"));
+        ui.checkbox(&mut config.deterministic, "Deterministic Timestep");
+        ui.horizontal(|ui| {
+            if recorder.recording {
+                if ui.button("Stop Recording").clicked() {
+                    recorder.recording = false;
+                }
+                ui.label(format!("{} frames", recorder.frames));
+            } else if ui.button("Start Recording").clicked() {
+                recorder.recording = true;
+            }
+        });
+    });
+
+    record_config.set_if_neq(config);
+}
+
+/// Records and plays back runs, and scrubs through the last one
+pub fn ui_replay_panel(
+    mut contexts: EguiContexts,
+    mut replay: ResMut<Replay>,
+) {
+    egui::Window::new("Replay").show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Replay File");
+            ui.add_enabled(replay.mode == ReplayMode::Idle, egui::TextEdit::singleline(&mut replay.path));
+        });
+
+        ui.horizontal(|ui| match replay.mode {
+            ReplayMode::Idle => {
+                if ui.button("Record").clicked() {
+                    replay.scrub = None;
+                    replay.requested = Some(ReplayMode::Recording);
+                }
+                if ui.button("Play").clicked() {
+                    replay.scrub = None;
+                    replay.requested = Some(ReplayMode::Playing);
+                }
+            }
+            ReplayMode::Recording => {
+                if ui.button("Stop Recording").clicked() {
+                    replay.requested = Some(ReplayMode::Idle);
+                }
+                ui.label(format!("{} frames", replay.frame));
+            }
+            ReplayMode::Playing => {
+                if ui.button("Stop Playing").clicked() {
+                    replay.requested = Some(ReplayMode::Idle);
+                }
+                let frames = replay.timeline.as_ref().map_or(0, |timeline| timeline.frames.len());
+                ui.label(format!("frame {} of {}", replay.frame, frames));
+            }
+        });
+
+        if let Some(frame) = replay.diverged_at {
+            ui.colored_label(egui::Color32::LIGHT_RED, format!("Diverged from the recording at frame {}", frame));
+        }
+
+        let frames = replay.timeline.as_ref().map_or(0, |timeline| timeline.frames.len());
+        if replay.mode == ReplayMode::Idle && frames > 0 {
+            let mut scrubbing = replay.scrub.is_some();
+            ui.checkbox(&mut scrubbing, "Scrub Timeline");
+            if scrubbing {
+                let mut frame = replay.scrub.unwrap_or(frames - 1);
+                ui.add(egui::Slider::new(&mut frame, 0..=frames - 1).text("Frame"));
+                replay.scrub = Some(frame);
+            } else if replay.scrub.is_some() {
+                replay.scrub = None;
+            }
+        }
+    });
+}
+
+/// Summary statistics of the current graph
+pub fn ui_analytics_panel(
+    mut contexts: EguiContexts,
+    stats: Res<GraphStats>,
+) {
+    egui::Window::new("Graph Analytics").default_open(false).show(contexts.ctx_mut(), |ui| {
+        egui::Grid::new("graph_stats").num_columns(2).show(ui, |ui| {
+            ui.label("Nodes");
+            ui.label(stats.nodes.to_string());
+            ui.end_row();
+            ui.label("Edges");
+            ui.label(stats.edges.to_string());
+            ui.end_row();
+            ui.label("Components");
+            ui.label(format!("{} (largest has {} nodes)", stats.components, stats.largest_component));
+            ui.end_row();
+            ui.label("Average Degree");
+            ui.label(format!("{:.2}", stats.average_degree));
+            ui.end_row();
+            ui.label("Average Clustering");
+            ui.label(format!("{:.3}", stats.average_clustering));
+            ui.end_row();
+            ui.label("Diameter");
+            ui.label(stats.diameter.to_string());
+            ui.end_row();
+            ui.label("Density");
+            ui.label(format!("{:.4}", stats.density));
+            ui.end_row();
+        });
+
+        ui.label("Degree Distribution");
+        histogram(ui, &stats.degree_histogram);
+    });
+}
+
+/// A line through the values, oldest on the left, labelled with the range it covers
+fn line_graph(ui: &mut egui::Ui, values: &[f32]) {
+    let size = egui::vec2(ui.available_width().max(200.), 100.);
+    let (response, painter) = ui.allocate_painter(size, egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_stroke(rect, 0., egui::Stroke::new(1f32, egui::Color32::DARK_GRAY));
+
+    let (min, max) = values.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    if values.len() < 2 {
+        return;
+    }
+    let range = (max - min).max(f32::EPSILON);
+    let points: Vec<egui::Pos2> = values.iter().enumerate()
+        .map(|(i, &v)| egui::pos2(
+            rect.left() + rect.width() * i as f32 / (values.len() - 1) as f32,
+            rect.bottom() - rect.height() * (v - min) / range,
+        ))
+        .collect();
+    painter.add(egui::Shape::line(points, egui::Stroke::new(1.5f32, egui::Color32::LIGHT_BLUE)));
+    painter.text(rect.left_top(), egui::Align2::LEFT_TOP, format!("{:.3}", max), egui::FontId::monospace(10.), egui::Color32::GRAY);
+    painter.text(rect.left_bottom(), egui::Align2::LEFT_BOTTOM, format!("{:.3}", min), egui::FontId::monospace(10.), egui::Color32::GRAY);
+}
+
+/// A bar for each count, labelled with its index underneath
+fn histogram(ui: &mut egui::Ui, counts: &[usize]) {
+    let max = counts.iter().cloned().max().unwrap_or(0).max(1);
+    let size = egui::vec2(ui.available_width().max(200.), 100.);
+    let (response, painter) = ui.allocate_painter(size, egui::Sense::hover());
+    let rect = response.rect;
+    let label_height = 14.;
+    let bar_width = rect.width() / counts.len().max(1) as f32;
+
+    for (i, &count) in counts.iter().enumerate() {
+        let height = (rect.height() - label_height) * count as f32 / max as f32;
+        let x = rect.left() + i as f32 * bar_width;
+        let bottom = rect.bottom() - label_height;
+        let bar = egui::Rect::from_min_max(egui::pos2(x + 1., bottom - height), egui::pos2(x + bar_width - 1., bottom));
+        painter.rect_filled(bar, 0., egui::Color32::LIGHT_BLUE);
+        painter.text(egui::pos2(x + bar_width / 2., rect.bottom()), egui::Align2::CENTER_BOTTOM, i.to_string(), egui::FontId::monospace(10.), egui::Color32::GRAY);
+    }
+
+    if let Some(pos) = response.hover_pos() {
+        let i = ((pos.x - rect.left()) / bar_width) as usize;
+        if let Some(count) = counts.get(i) {
+            response.on_hover_text(format!("degree {}: {} nodes", i, count));
+        }
+    }
+}
+
+/// Picks the algorithm used to find a path between two clicked Dots
+pub fn ui_path_panel(
+    mut contexts: EguiContexts,
+    mut pathfinding_config: ResMut<PathfindingConfig>,
+    mut selection: ResMut<PathSelection>,
+) {
+    // the path is searched for again whenever the config changes, so only write it back when it's
+    // actually edited
+    let mut config = pathfinding_config.clone();
+
+    egui::Window::new("Shortest Path").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.checkbox(&mut config.enabled, "Click Dots To Pick Ends");
+
+        egui::ComboBox::from_label("Algorithm")
+            .selected_text(format!("{:?}", config.algorithm))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut config.algorithm, PathAlgorithm::BreadthFirst, "BreadthFirst");
+                ui.selectable_value(&mut config.algorithm, PathAlgorithm::Dijkstra, "Dijkstra");
+                ui.selectable_value(&mut config.algorithm, PathAlgorithm::AStar, "AStar");
+            });
+        if config.algorithm != PathAlgorithm::BreadthFirst {
+            egui::ComboBox::from_label("Cost")
+                .selected_text(format!("{:?}", config.cost))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut config.cost, PathCost::Length, "Length");
+                    ui.selectable_value(&mut config.cost, PathCost::Weight, "Weight");
+                });
+        }
+        ui.add(egui::Slider::new(&mut config.wavefront_seconds, 0.1..=10.0).text("Wavefront Seconds"));
+
+        match (selection.start, selection.goal) {
+            (None, _) => ui.label("Click a dot to start from"),
+            (Some(_), None) => ui.label("Click a dot to go to"),
+            (Some(_), Some(_)) if selection.path.is_empty() => ui.label("No path"),
+            (Some(_), Some(_)) => ui.label(format!("{} hops, {} dots searched", selection.path.len() - 1, selection.expansion.iter().map(Vec::len).sum::<usize>())),
+        };
+        if ui.button("Clear").clicked() {
+            selection.start = None;
+            selection.goal = None;
+        }
+    });
+
+    pathfinding_config.set_if_neq(config);
+}
+
+/// Every Dot's centralities, sorted by whichever column was clicked last. Clicking it again
+/// flips the order.
+pub fn ui_centrality_panel(
+    mut contexts: EguiContexts,
+    dots_q: Query<(Entity, &Centrality, Option<&Name>), With<Dot>>,
+    mut sort: Local<Option<(CentralityMeasure, bool)>>,
+) {
+    egui::Window::new("Centrality").default_open(false).show(contexts.ctx_mut(), |ui| {
+        let (measure, descending) = sort.unwrap_or((CentralityMeasure::Degree, true));
+        let mut rows: Vec<(String, &Centrality)> = dots_q.iter()
+            .map(|(eid, centrality, name)| (dot_label_text(eid, name), centrality))
+            .collect();
+        rows.sort_by(|(_, a), (_, b)| measure.value(a).total_cmp(&measure.value(b)));
+        if descending {
+            rows.reverse();
+        }
+
+        egui::ScrollArea::vertical().max_height(300.).show(ui, |ui| {
+            egui::Grid::new("centrality").num_columns(CentralityMeasure::ALL.len() + 1).striped(true).show(ui, |ui| {
+                ui.label("Dot");
+                for column in CentralityMeasure::ALL {
+                    let arrow = match (column == measure, descending) {
+                        (false, _) => "",
+                        (true, true) => " v",
+                        (true, false) => " ^",
+                    };
+                    if ui.button(format!("{}{}", column.name(), arrow)).clicked() {
+                        *sort = Some((column, column != measure || !descending));
+                    }
+                }
+                ui.end_row();
+
+                for (label, centrality) in rows.iter() {
+                    ui.label(label);
+                    for column in CentralityMeasure::ALL {
+                        ui.label(format!("{:.4}", column.value(centrality)));
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+    });
+}
+
+pub fn ui_quality_panel(
+    mut contexts: EguiContexts,
+    mut config: ResMut<LayoutQualityConfig>,
+    log: Res<LayoutQualityLog>,
+) {
+    egui::Window::new("Layout Quality").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut config.continuous, "Continuous");
+            if ui.button("Measure Now").clicked() {
+                config.requested = true;
+            }
+        });
+        if config.continuous {
+            ui.add(egui::Slider::new(&mut config.interval, 1..=120).text("Frames Between Samples"));
+        }
+
+        let Some(latest) = log.latest() else {
+            ui.label("Not measured yet");
+            return;
+        };
+        egui::Grid::new("layout_quality").num_columns(2).show(ui, |ui| {
+            for metric in QualityMetric::ALL {
+                ui.label(metric.name());
+                ui.label(format!("{:.3}", metric.value(latest)));
+                ui.end_row();
+            }
+        });
+
+        egui::ComboBox::from_label("Graph")
+            .selected_text(config.graphed.name())
+            .show_ui(ui, |ui| {
+                for metric in QualityMetric::ALL {
+                    ui.selectable_value(&mut config.graphed, metric, metric.name());
+                }
+            });
+        let values: Vec<f32> = log.samples.iter().map(|(_, quality)| config.graphed.value(quality)).collect();
+        line_graph(ui, &values);
+    });
+}
+
+/// The Dot whose mass and charge are being edited
+#[derive(Resource, Default)]
+pub struct Inspector {
+    /// Clicking a Dot selects it
+    pub picking: bool,
+    pub dot: Option<Entity>,
+}
+
+/// Edits the mass and charge of a single Dot, picked by clicking it
+pub fn ui_inspector_panel(
+    mut contexts: EguiContexts,
+    mut inspector: ResMut<Inspector>,
+    mut dots_q: Query<(&mut Mass, &mut Charge, &Neighbors, Option<&Name>), With<Dot>>,
+    physics_config: Res<PhysicsConfig>,
+    replay: Res<Replay>,
+) {
+    egui::Window::new("Dot Inspector").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.checkbox(&mut inspector.picking, "Click To Select");
+
+        let Some(eid) = inspector.dot else {
+            ui.label("No dot selected");
+            return;
+        };
+        let Ok((mut dot_mass, mut dot_charge, neighbors, name)) = dots_q.get_mut(eid) else {
+            // the dot was despawned
+            inspector.dot = None;
+            return;
+        };
+
+        ui.label(format!("Dot {}", dot_label_text(eid, name)));
+        // replays only record changes to the configuration, so edits to a single Dot would be lost
+        let editable = replay.mode == ReplayMode::Idle;
+        let (mut mass, mut charge) = (*dot_mass, *dot_charge);
+        ui.add_enabled(editable, egui::DragValue::new(&mut mass.0).speed(0.05).clamp_range(0.01..=100.0).prefix("Mass "));
+        ui.add_enabled(editable, egui::DragValue::new(&mut charge.0).speed(0.05).clamp_range(-100.0..=100.0).prefix("Charge "));
+        dot_mass.set_if_neq(mass);
+        dot_charge.set_if_neq(charge);
+        ui.label(format!(
+            "Scaled by degree: mass {:.2}, charge {:.2}",
+            physics_config.dot_mass(&mass, neighbors),
+            physics_config.dot_charge(&charge, neighbors),
+        ));
+    });
+}
+
+/// While picking, clicking a Dot selects it for the inspector
+pub fn select_inspected_dot(
+    dots_q: Query<(Entity, &Transform, &DotStyle), With<Dot>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    mouse: Res<MousePosition>,
+    mut inspector: ResMut<Inspector>,
+    mut contexts: EguiContexts,
+) {
+    if !inspector.picking || !buttons.just_pressed(MouseButton::Left) || contexts.ctx_mut().wants_pointer_input() {
+        return;
+    }
+    if let Some(clicked) = dot_at(dots_q.iter(), mouse.0) {
+        inspector.dot = Some(clicked);
+    }
+}
+
+/// A labelled row of x and y values
+fn vec2_drag(ui: &mut egui::Ui, label: &str, v: &mut bevy::math::Vec2) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(egui::DragValue::new(&mut v.x).prefix("x "));
+        ui.add(egui::DragValue::new(&mut v.y).prefix("y "));
+    });
+}
+
+/// Edits the position and size of a shape, or the vertices of a polygon
+fn shape_editor(ui: &mut egui::Ui, shape: &mut BoundaryShape) {
+    match shape {
+        BoundaryShape::Rectangle { min, max } => {
+            vec2_drag(ui, "Min", min);
+            vec2_drag(ui, "Max", max);
+        }
+        BoundaryShape::Circle { center, radius } => {
+            vec2_drag(ui, "Center", center);
+            ui.add(egui::DragValue::new(radius).clamp_range(0.0..=f32::MAX).prefix("Radius "));
+        }
+        BoundaryShape::Polygon { vertices } => {
+            let mut removed = None;
+            for (i, vertex) in vertices.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    vec2_drag(ui, &format!("{}", i), vertex);
+                    if ui.button("Remove").clicked() {
+                        removed = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = removed {
+                vertices.remove(i);
+            }
+            if ui.button("Add Vertex").clicked() {
+                // halfway along the closing edge, so the outline barely changes
+                let vertex = match (vertices.first(), vertices.last()) {
+                    (Some(first), Some(last)) => (*first + *last) / 2.,
+                    _ => bevy::math::Vec2::ZERO,
+                };
+                vertices.push(vertex);
+            }
+            if vertices.len() < 3 {
+                ui.label("Needs at least 3 vertices to enclose anything");
+            }
+        }
+        BoundaryShape::None => {}
+    }
+}
+
+pub fn ui_boundary_panel(
+    mut contexts: EguiContexts,
+    mut boundary: ResMut<Boundary>,
+) {
+    egui::Window::new("Boundary").default_open(false).show(contexts.ctx_mut(), |ui| {
+        let shape_name = |shape: &BoundaryShape| match shape {
+            BoundaryShape::Rectangle { .. } => "Rectangle",
+            BoundaryShape::Circle { .. } => "Circle",
+            BoundaryShape::Polygon { .. } => "Polygon",
+            BoundaryShape::None => "None",
+        };
+        egui::ComboBox::from_label("Shape")
+            .selected_text(shape_name(&boundary.shape))
+            .show_ui(ui, |ui| {
+                // picking a different shape starts it off fitted to the window
+                let shapes = [BoundaryShape::window_rectangle(), BoundaryShape::window_circle(), BoundaryShape::window_polygon(), BoundaryShape::None];
+                for shape in shapes {
+                    let selected = std::mem::discriminant(&boundary.shape) == std::mem::discriminant(&shape);
+                    if ui.selectable_label(selected, shape_name(&shape)).clicked() && !selected {
+                        boundary.shape = shape;
+                    }
+                }
+            });
+
+        shape_editor(ui, &mut boundary.shape);
+
+        egui::ComboBox::from_label("Walls")
+            .selected_text(format!("{:?}", boundary.behaviour))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut boundary.behaviour, WallBehaviour::SoftRepulsion, "SoftRepulsion");
+                ui.selectable_value(&mut boundary.behaviour, WallBehaviour::Clamp, "Clamp");
+                ui.selectable_value(&mut boundary.behaviour, WallBehaviour::Bounce, "Bounce");
+                ui.selectable_value(&mut boundary.behaviour, WallBehaviour::Wrap, "Wrap");
+            });
+        if boundary.behaviour == WallBehaviour::Bounce {
+            ui.add(egui::Slider::new(&mut boundary.restitution, 0.0..=1.0).text("Restitution"));
+        }
     });
-}
\ No newline at end of file
+}
+
+/// Places and edits obstacles and force fields, and saves them with the physics and boundary as a
+/// preset
+pub fn ui_obstacles_panel(
+    mut contexts: EguiContexts,
+    mut commands: Commands,
+    mut obstacles: ResMut<Obstacles>,
+    boundary: Res<Boundary>,
+) {
+    egui::Window::new("Obstacles").default_open(false).show(contexts.ctx_mut(), |ui| {
+        // new things go in the middle of the boundary, where they're easy to find
+        let center = boundary.shape.center();
+        ui.horizontal(|ui| {
+            if ui.button("Add Circle").clicked() {
+                obstacles.obstacles.push(Obstacle::circle(center));
+            }
+            if ui.button("Add Rectangle").clicked() {
+                obstacles.obstacles.push(Obstacle::rectangle(center));
+            }
+            if ui.button("Add Polygon").clicked() {
+                obstacles.obstacles.push(Obstacle::polygon(center));
+            }
+        });
+
+        let mut removed = None;
+        for (i, obstacle) in obstacles.obstacles.iter_mut().enumerate() {
+            ui.separator();
+            ui.push_id(("obstacle", i), |ui| {
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_label(format!("Obstacle {}", i))
+                        .selected_text(format!("{:?}", obstacle.behaviour))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut obstacle.behaviour, ObstacleBehaviour::Collide, "Collide");
+                            ui.selectable_value(&mut obstacle.behaviour, ObstacleBehaviour::Repel, "Repel");
+                        });
+                    if ui.button("Remove").clicked() {
+                        removed = Some(i);
+                    }
+                });
+                shape_editor(ui, &mut obstacle.shape);
+            });
+        }
+        if let Some(i) = removed {
+            obstacles.obstacles.remove(i);
+        }
+
+        ui.separator();
+        if ui.button("Add Force Field").clicked() {
+            obstacles.fields.push(ForceField::new(center));
+        }
+        let mut removed = None;
+        for (i, field) in obstacles.fields.iter_mut().enumerate() {
+            ui.separator();
+            ui.push_id(("field", i), |ui| {
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_label(format!("Field {} Falloff", i))
+                        .selected_text(format!("{:?}", field.falloff))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut field.falloff, FieldFalloff::Constant, "Constant");
+                            ui.selectable_value(&mut field.falloff, FieldFalloff::Linear, "Linear");
+                            ui.selectable_value(&mut field.falloff, FieldFalloff::Quadratic, "Quadratic");
+                        });
+                    if ui.button("Remove").clicked() {
+                        removed = Some(i);
+                    }
+                });
+                vec2_drag(ui, "Center", &mut field.center);
+                ui.add(egui::DragValue::new(&mut field.radius).clamp_range(0.0..=f32::MAX).prefix("Radius "));
+                ui.add(egui::Slider::new(&mut field.strength, -2.0..=2.0).text("Strength (negative repels)"));
+            });
+        }
+        if let Some(i) = removed {
+            obstacles.fields.remove(i);
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            // presets cover resources this panel doesn't edit, so they're read and written once
+            // the world is free
+            if ui.button("Save Preset").clicked() {
+                commands.add(|world: &mut World| {
+                    if let Err(e) = Preset::capture(world).save(PRESET_PATH) {
+                        error!("could not save preset \"{}\": {}", PRESET_PATH, e);
+                    }
+                });
+            }
+            if ui.button("Load Preset").clicked() {
+                match Preset::load(PRESET_PATH) {
+                    Ok(preset) => commands.add(|world: &mut World| preset.apply(world)),
+                    Err(e) => warn!("could not load preset \"{}\": {}", PRESET_PATH, e),
+                }
+            }
+        });
+    });
+}
+
+pub fn ui_community_panel(
+    mut contexts: EguiContexts,
+    mut community_config: ResMut<CommunityConfig>,
+    mut physics_config: ResMut<PhysicsConfig>,
+    stats: Res<CommunityStats>,
+) {
+    // communities are detected again whenever the config changes
+    let mut config = community_config.clone();
+
+    egui::Window::new("Communities").default_open(false).show(contexts.ctx_mut(), |ui| {
+        egui::ComboBox::from_label("Method")
+            .selected_text(format!("{:?}", config.method))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut config.method, CommunityMethod::Louvain, "Louvain");
+                ui.selectable_value(&mut config.method, CommunityMethod::Leiden, "Leiden");
+                ui.selectable_value(&mut config.method, CommunityMethod::LabelPropagation, "LabelPropagation");
+            });
+        if config.method != CommunityMethod::LabelPropagation {
+            ui.add(egui::Slider::new(&mut config.resolution, 0.1..=5.0).text("Resolution"));
+        }
+        ui.add(egui::Slider::new(&mut physics_config.community_attraction, 0.0..=0.05).text("Community Attraction"));
+
+        ui.label(format!("{} communities, modularity {:.3}", stats.count, stats.modularity));
+    });
+
+    community_config.set_if_neq(config);
+}
+
+/// Explains what the current dot and edge styles mean
+pub fn ui_legend_panel(
+    mut contexts: EguiContexts,
+    legend: Res<Legend>,
+) {
+    if matches!((&legend.dot_color, &legend.dot_size, &legend.edge_color), (LegendScale::None, LegendScale::None, LegendScale::None)) {
+        return;
+    }
+
+    egui::Window::new("Legend").show(contexts.ctx_mut(), |ui| {
+        legend_scale(ui, "Dot Colour", &legend.dot_color, true);
+        legend_scale(ui, "Dot Size", &legend.dot_size, false);
+        legend_scale(ui, "Edge Colour", &legend.edge_color, true);
+    });
+}
+
+fn legend_scale(ui: &mut egui::Ui, title: &str, scale: &LegendScale, colored: bool) {
+    match scale {
+        LegendScale::None => {}
+        LegendScale::Ramp { label, min, max, ramp } => {
+            ui.label(format!("{}: {}", title, label));
+            if colored {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{:.2}", min));
+                    ui.spacing_mut().item_spacing.x = 0.;
+                    for i in 0..=10 {
+                        color_swatch(ui, ramp.sample(i as f32 / 10.));
+                    }
+                    ui.spacing_mut().item_spacing.x = 8.;
+                    ui.label(format!("{:.2}", max));
+                });
+            } else {
+                ui.label(format!("small = {:.2}, large = {:.2}", min, max));
+            }
+        }
+        LegendScale::Categories(categories) => {
+            ui.label(title);
+            for (name, color) in categories {
+                ui.horizontal(|ui| {
+                    color_swatch(ui, *color);
+                    ui.label(name);
+                });
+            }
+        }
+    }
+}
+
+fn color_swatch(ui: &mut egui::Ui, color: bevy::prelude::Color) {
+    let [r, g, b, _] = color.as_rgba_u8();
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(12., 12.), egui::Sense::hover());
+    ui.painter().rect_filled(rect, 0., egui::Color32::from_rgb(r, g, b));
+}